@@ -0,0 +1,271 @@
+use std::fs;
+use std::io::{self, Write};
+
+use bobba_asset_extractor::visualization::Visualization;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+
+use crate::asset_extraction::AssetPack;
+use crate::bitmap::RgbaImage;
+use crate::render::{self, Bounds, Pose, Renderer};
+use crate::runtime;
+
+/// Animation ticks per second of the client.
+pub(crate) const TICK_RATE: u16 = 24;
+/// Upper bound on the frames of one animation, looping layers with unrelated lengths explode otherwise.
+const MAX_FRAMES: u32 = 480;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Choices for the animated output, empty filters select everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct AnimationOptions {
+    pub gif: bool,
+    pub directions: Vec<u32>,
+    pub states: Vec<u32>,
+}
+
+/// Length of the animation for the state in ticks, and whether it loops forever.
+///
+/// NOTE; The animation lasts until every layer is back at its first frame, layers which stop after their
+/// loop count make the whole animation play once.
+pub(crate) fn timeline(visualization: &Visualization, state: u32) -> (u32, bool) {
+    let animation = match visualization.animation(state) {
+        Some(animation) => animation,
+        None => return (1, true),
+    };
+
+    let mut cycle = 1u32;
+    let mut finite = 0u32;
+    for layer in animation.layers.iter() {
+        let frame_count = render::sequence_frames(layer).len() as u32;
+        let layer_cycle = frame_count.max(1) * layer.frame_repeat.unwrap_or(1).max(1);
+        match layer.loop_count {
+            Some(loops) if loops > 0 => finite = finite.max(layer_cycle * loops),
+            _ => cycle = least_common_multiple(cycle, layer_cycle).min(MAX_FRAMES),
+        }
+    }
+    match finite {
+        0 => (cycle, true),
+        finite => (finite.max(cycle).min(MAX_FRAMES), false),
+    }
+}
+
+fn least_common_multiple(a: u32, b: u32) -> u32 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let remainder = x % y;
+        x = y;
+        y = remainder;
+    }
+    (a / x).saturating_mul(b)
+}
+
+/// Renders every tick of the state, all frames share one canvas.
+pub(crate) fn render_frames(renderer: &Renderer, pose: &Pose, frame_count: u32) -> Vec<RgbaImage> {
+    let placements: Vec<_> = (0..frame_count)
+        .map(|frame| renderer.placements(&Pose { frame, ..*pose }))
+        .collect();
+    let bounds = placements
+        .iter()
+        .filter_map(|frame| Bounds::of(frame))
+        .fold(None, |bounds: Option<Bounds>, next| {
+            Some(bounds.map_or(next, |bounds| bounds.union(next)))
+        });
+    match bounds {
+        Some(bounds) => placements
+            .iter()
+            .map(|frame| render::compose(frame, bounds))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn write_chunk(buffer: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    buffer.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(kind);
+    buffer.extend_from_slice(data);
+    buffer.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Filters (none) and compresses the pixels into the content of an IDAT chunk.
+fn compress_image(image: &RgbaImage) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    let row_length = image.width as usize * 4;
+    if row_length > 0 {
+        for row in image.pixels.chunks(row_length) {
+            encoder.write_all(&[0])?;
+            encoder.write_all(row)?;
+        }
+    }
+    encoder.finish()
+}
+
+/// Encodes the frames as animated PNG, `plays` 0 loops forever.
+///
+/// NOTE; The png crate can't write APNG, the chunks are written by hand. Every frame covers the whole
+/// canvas and replaces the previous one.
+pub(crate) fn encode_apng(frames: &[RgbaImage], plays: u32) -> io::Result<Vec<u8>> {
+    let first = match frames.first() {
+        Some(first) => first,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "No frames")),
+    };
+    let mut buffer = PNG_SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&first.width.to_be_bytes());
+    header.extend_from_slice(&first.height.to_be_bytes());
+    // NOTE; Bit depth 8, color type RGBA, deflate, adaptive filtering, no interlace.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut buffer, b"IHDR", &header);
+
+    let mut control = Vec::with_capacity(8);
+    control.extend_from_slice(&(frames.len() as u32).to_be_bytes());
+    control.extend_from_slice(&plays.to_be_bytes());
+    write_chunk(&mut buffer, b"acTL", &control);
+
+    let mut sequence = 0u32;
+    for (index, frame) in frames.iter().enumerate() {
+        let mut frame_control = Vec::with_capacity(26);
+        frame_control.extend_from_slice(&sequence.to_be_bytes());
+        frame_control.extend_from_slice(&frame.width.to_be_bytes());
+        frame_control.extend_from_slice(&frame.height.to_be_bytes());
+        frame_control.extend_from_slice(&0u32.to_be_bytes());
+        frame_control.extend_from_slice(&0u32.to_be_bytes());
+        frame_control.extend_from_slice(&1u16.to_be_bytes());
+        frame_control.extend_from_slice(&TICK_RATE.to_be_bytes());
+        // NOTE; Dispose to transparent black, blend by replacing the canvas.
+        frame_control.extend_from_slice(&[1, 0]);
+        write_chunk(&mut buffer, b"fcTL", &frame_control);
+        sequence += 1;
+
+        let image_data = compress_image(frame)?;
+        if index == 0 {
+            write_chunk(&mut buffer, b"IDAT", &image_data);
+        } else {
+            let mut frame_data = Vec::with_capacity(4 + image_data.len());
+            frame_data.extend_from_slice(&sequence.to_be_bytes());
+            frame_data.extend_from_slice(&image_data);
+            write_chunk(&mut buffer, b"fdAT", &frame_data);
+            sequence += 1;
+        }
+    }
+
+    write_chunk(&mut buffer, b"IEND", &[]);
+    Ok(buffer)
+}
+
+/// Encodes the frames as GIF, `plays` 0 loops forever.
+///
+/// NOTE; GIF delays are counted in hundredths of a second, the tick rate is rounded. Partial transparency
+/// is lost to the single transparent palette entry.
+pub(crate) fn encode_gif(frames: &[RgbaImage], plays: u32) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    {
+        let (width, height) = frames
+            .first()
+            .map(|first| (first.width as u16, first.height as u16))
+            .unwrap_or((0, 0));
+        let mut encoder = gif::Encoder::new(&mut buffer, width, height, &[])?;
+        // NOTE; Without repetition extension the GIF plays once, its count excludes the first play.
+        match plays {
+            0 => encoder.write_extension(gif::ExtensionData::Repetitions(gif::Repeat::Infinite))?,
+            1 => {}
+            plays => encoder.write_extension(gif::ExtensionData::Repetitions(
+                gif::Repeat::Finite((plays - 1) as u16),
+            ))?,
+        }
+
+        for image in frames.iter() {
+            let mut pixels = image.pixels.clone();
+            let mut frame = gif::Frame::from_rgba_speed(
+                image.width as u16,
+                image.height as u16,
+                &mut pixels,
+                10,
+            );
+            frame.delay = (100.0 / TICK_RATE as f32).round() as u16;
+            frame.dispose = gif::DisposalMethod::Background;
+            encoder.write_frame(&frame)?;
+        }
+    }
+    Ok(buffer)
+}
+
+/// Writes every animation state of the asset pack into `<extract folder>/animation/`.
+pub(crate) fn write_animations(
+    pack: &AssetPack,
+    options: &AnimationOptions,
+    logger: &slog::Logger,
+) -> Result<(), runtime::Error> {
+    let extract_path = pack.extract_path();
+    let renderer = match Renderer::load(&extract_path, &pack.asset_name, logger)? {
+        Some(renderer) => renderer,
+        None => return Ok(()),
+    };
+    let visualization = match renderer.visualization(render::PREVIEW_SIZE) {
+        Some(visualization) => visualization,
+        None => return Ok(()),
+    };
+
+    let directions: Vec<u32> = visualization
+        .direction_ids()
+        .into_iter()
+        .filter(|direction| options.directions.is_empty() || options.directions.contains(direction))
+        .collect();
+    let states: Vec<u32> = visualization
+        .animations
+        .items
+        .iter()
+        .map(|animation| animation.id)
+        .filter(|state| options.states.is_empty() || options.states.contains(state))
+        .collect();
+    if directions.is_empty() || states.is_empty() {
+        return Ok(());
+    }
+
+    let animation_path = extract_path.join("animation");
+    fs::create_dir_all(&animation_path).map_err(runtime::Error::Io)?;
+    let mut written = 0usize;
+    for &direction in directions.iter() {
+        for &state in states.iter() {
+            let (frame_count, loops_forever) = timeline(visualization, state);
+            let pose = Pose {
+                size: render::PREVIEW_SIZE,
+                direction,
+                state,
+                ..Pose::default()
+            };
+            let frames = render_frames(&renderer, &pose, frame_count);
+            if frames.is_empty() {
+                continue;
+            }
+
+            let plays = match loops_forever {
+                true => 0,
+                false => 1,
+            };
+            let file_stem = format!(
+                "{}_{}_{}_{}",
+                pack.asset_name,
+                render::PREVIEW_SIZE,
+                direction,
+                state
+            );
+            let apng_data = encode_apng(&frames, plays).map_err(runtime::Error::Io)?;
+            fs::write(animation_path.join(format!("{}.png", file_stem)), apng_data)
+                .map_err(runtime::Error::Io)?;
+            if options.gif {
+                let gif_data = encode_gif(&frames, plays).map_err(runtime::Error::Io)?;
+                fs::write(animation_path.join(format!("{}.gif", file_stem)), gif_data)
+                    .map_err(runtime::Error::Io)?;
+            }
+            written += 1;
+        }
+    }
+    slog::debug!(logger, "Animations rendered"; "asset" => &pack.asset_name, "animations" => written);
+    Ok(())
+}
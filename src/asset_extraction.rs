@@ -1,8 +1,969 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
-pub(crate) fn  download_asset_packs() {
-    todo!()
+use bobba_asset_extractor::{assets, index, logic, visualization};
+use reqwest::blocking::Client;
+use reqwest::{StatusCode, Url};
+use swf::{read_swf, Tag};
+
+use crate::atlas;
+use crate::bitmap;
+use crate::cache::{self, FetchOutcome};
+use crate::cli::OutputFormat;
+use crate::furni_document;
+use crate::furnidata::FurnitureType;
+use crate::hotel::Hotel;
+use crate::nitro;
+use crate::runtime::{self, RuntimeData};
+use crate::swf_container;
+use crate::throttle::HostLimiter;
+
+/// One SWF file on the asset server, shared by all furniture using the same asset name.
+#[derive(Debug, Clone)]
+pub(crate) struct AssetPack {
+    pub runtime: RuntimeData,
+    /// Name of the hotel serving the asset pack.
+    pub hotel: String,
+    pub asset_name: String,
+    pub revision: u32,
+    pub url: Url,
+    pub swf_path: PathBuf,
+    /// All furniture data rows using this asset pack, eg every color variant.
+    pub furniture: Vec<FurnitureType>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DownloadStatus {
+    Ok,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct DownloadReport {
+    pub ok: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl DownloadReport {
+    pub fn record(&mut self, status: DownloadStatus) {
+        match status {
+            DownloadStatus::Ok => self.ok += 1,
+            DownloadStatus::Skipped => self.skipped += 1,
+            DownloadStatus::Failed => self.failed += 1,
+        }
+    }
+}
+
+impl fmt::Display for DownloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} downloaded, {} skipped, {} failed",
+            self.ok, self.skipped, self.failed
+        )
+    }
+}
+
+/// Retry behaviour for transient download failures, the delay doubles after each attempt.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetryPolicy {
+    pub attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::max_value());
+        std::cmp::min(
+            self.base_delay
+                .checked_mul(factor)
+                .unwrap_or(self.max_delay),
+            self.max_delay,
+        )
+    }
+}
+
+/// Too many requests, server errors and connection problems are worth another attempt.
+fn is_transient(error: &runtime::Error) -> bool {
+    match *error {
+        runtime::Error::HttpClient(ref reqwest) => match reqwest.status() {
+            Some(status) => status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            None => !reqwest.is_builder() && !reqwest.is_redirect(),
+        },
+        _ => false,
+    }
+}
+
+/// Revision folder of the asset pack on the asset server.
+///
+/// NOTE; A pinned runtime forces its own revision onto every asset pack.
+fn asset_revision(runtime: &RuntimeData, revision: u32) -> String {
+    match runtime.is_pinned() {
+        true => runtime.get_revision().to_string(),
+        false => revision.to_string(),
+    }
+}
+
+/// Builds the url of the asset pack, `<furni url>/<revision>/<asset name>.swf`.
+pub(crate) fn asset_pack_url(
+    runtime: &RuntimeData,
+    hotel: &Hotel,
+    asset_name: &str,
+    revision: u32,
+) -> Result<Url, runtime::Error> {
+    let revision = asset_revision(runtime, revision);
+    let file_name = format!("{base_name}.swf", base_name = asset_name);
+
+    let mut url = hotel.furni_url.clone();
+    url.path_segments_mut()
+        .map_err(|_| runtime::Error::InvalidUrl(hotel.furni_url.to_string()))?
+        .pop_if_empty()
+        .extend(&[&revision, &file_name]);
+    Ok(url)
+}
+
+/// Groups the furniture by asset name, each asset pack is downloaded once.
+///
+/// NOTE; Color variants might carry different revisions, the newest revision is used.
+pub(crate) fn asset_packs(
+    runtime: &RuntimeData,
+    hotel: &Hotel,
+    furniture: &BTreeMap<u32, FurnitureType>,
+) -> Result<Vec<AssetPack>, runtime::Error> {
+    let grouped = furniture.values().fold(
+        BTreeMap::<&str, Vec<FurnitureType>>::new(),
+        |mut acc, item| {
+            acc.entry(item.asset_name())
+                .or_insert_with(Vec::new)
+                .push(item.clone());
+            acc
+        },
+    );
+
+    grouped
+        .into_iter()
+        .map(|(asset_name, furniture)| {
+            let revision = furniture
+                .iter()
+                .map(FurnitureType::revision)
+                .max()
+                .unwrap_or(0);
+            let url = asset_pack_url(runtime, hotel, asset_name, revision)?;
+            let swf_path = runtime
+                .get_asset_path()
+                .join(asset_revision(runtime, revision))
+                .join(format!("{}.swf", asset_name));
+
+            Ok(AssetPack {
+                runtime: runtime.clone(),
+                hotel: hotel.name.clone(),
+                asset_name: String::from(asset_name),
+                revision,
+                url,
+                swf_path,
+                furniture,
+            })
+        })
+        .collect()
+}
+
+/// Downloads one asset pack, retrying transient failures with exponential backoff.
+pub(crate) fn download_asset_pack(
+    client: &Client,
+    pack: &AssetPack,
+    limiter: &HostLimiter,
+    policy: &RetryPolicy,
+    cache_time: Duration,
+    logger: &slog::Logger,
+) -> DownloadStatus {
+    let logger = logger.new(slog::o!("asset" => pack.asset_name.clone()));
+    if pack.revision == 0 && !pack.runtime.is_pinned() {
+        slog::debug!(logger, "SKIP; No specific revision");
+        return DownloadStatus::Skipped;
+    }
+
+    if let Some(parent) = pack.swf_path.parent() {
+        if let Err(error) = fs::create_dir_all(parent) {
+            slog::error!(logger, "FAIL; Asset folder unavailable"; "error" => %error);
+            return DownloadStatus::Failed;
+        }
+    }
+
+    let host = pack.url.host_str().unwrap_or_default().to_string();
+    let mut attempt = 0;
+    loop {
+        let result = {
+            let _permit = limiter.acquire(&host);
+            cache::fetch_validated(
+                client,
+                &pack.url,
+                &pack.swf_path,
+                cache_time,
+                &logger,
+                |path| {
+                    let data = fs::read(path).map_err(runtime::Error::Io)?;
+                    swf_container::validate(&data)
+                        .map_err(|reason| runtime::Error::InvalidSwf(pack.url.clone(), reason))
+                },
+            )
+        };
+
+        match result {
+            Ok(FetchOutcome::Cached) | Ok(FetchOutcome::NotModified) => {
+                slog::trace!(logger, "SKIP; Already downloaded");
+                return DownloadStatus::Skipped;
+            }
+            Ok(FetchOutcome::Downloaded(length)) => {
+                slog::trace!(logger, "OK; Downloaded"; "bytes" => length);
+                return DownloadStatus::Ok;
+            }
+            Err(ref error) if is_transient(error) && attempt + 1 < policy.attempts => {
+                let delay = policy.delay(attempt);
+                slog::warn!(logger, "Download failed, retrying";
+                    "error" => %error, "attempt" => attempt + 1, "delay" => ?delay);
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(error) => {
+                slog::error!(logger, "FAIL; Download failed"; "url" => %pack.url, "error" => %error);
+                return DownloadStatus::Failed;
+            }
+        }
+    }
+}
+
+/// Maps character ids onto symbol names, collected from every SymbolClass and ExportAssets tag.
+///
+/// NOTE; Tag order is not defined, a symbol might be declared after the character it names.
+pub(crate) fn collect_symbols(tags: &[Tag], asset_name: &str) -> BTreeMap<u16, String> {
+    // NOTE; Symbols are prefixed with the asset name and an additional underscore.
+    let prefix = format!("{}_", asset_name);
+    let trim = |name: &str| match name.starts_with(&prefix) {
+        true => String::from(&name[prefix.len()..]),
+        false => String::from(name),
+    };
+
+    let mut symbols = BTreeMap::new();
+    for tag in tags.iter() {
+        match tag {
+            Tag::SymbolClass(links) => {
+                for link in links.iter() {
+                    symbols
+                        .entry(link.id as u16)
+                        .or_insert_with(|| trim(&link.class_name));
+                }
+            }
+            Tag::ExportAssets(exports) => {
+                for export in exports.iter() {
+                    symbols
+                        .entry(export.id as u16)
+                        .or_insert_with(|| trim(&export.name));
+                }
+            }
+            _ => continue,
+        }
+    }
+    symbols
+}
+
+/// Choices for the output of the extraction stage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ExtractionOptions {
+    pub format: OutputFormat,
+    pub atlas_trim: bool,
+    pub atlas_max_size: u32,
+}
+
+/// Outcome of extracting one asset pack.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ExtractionReport {
+    pub written: usize,
+    pub skipped: usize,
+    /// Characters which never received a symbol name, with the kind of tag defining them.
+    pub unresolved: Vec<(u16, &'static str)>,
+}
+
+impl AssetPack {
+    /// Folder receiving the extracted assets, next to the SWF file.
+    pub fn extract_path(&self) -> PathBuf {
+        self.swf_path.with_extension("")
+    }
+}
+
+fn write_asset(
+    destination: &Path,
+    data: &[u8],
+    report: &mut ExtractionReport,
+) -> Result<(), runtime::Error> {
+    if destination.exists() {
+        report.skipped += 1;
+        return Ok(());
+    }
+    fs::write(destination, data).map_err(runtime::Error::Io)?;
+    report.written += 1;
+    Ok(())
+}
+
+/// Decodes the image of a character into a PNG file named after its symbol.
+///
+/// NOTE; Decoding is skipped when the PNG file already exists, and failures are logged but don't abort the
+/// extraction of the other characters.
+fn write_image<F>(
+    extract_path: &Path,
+    symbols: &BTreeMap<u16, String>,
+    (asset_id, kind): (u16, &'static str),
+    report: &mut ExtractionReport,
+    logger: &slog::Logger,
+    decode: F,
+) -> Result<(), runtime::Error>
+where
+    F: FnOnce() -> Result<bitmap::RgbaImage, bitmap::Error>,
+{
+    let file_stem = match symbols.get(&asset_id) {
+        Some(name) => name,
+        None => {
+            report.unresolved.push((asset_id, kind));
+            return Ok(());
+        }
+    };
+    let destination = extract_path.join(format!("{}.png", file_stem));
+    if destination.exists() {
+        report.skipped += 1;
+        return Ok(());
+    }
+
+    match decode().and_then(|image| image.encode_png()) {
+        Ok(png_data) => write_asset(&destination, &png_data, report),
+        Err(error) => {
+            slog::warn!(logger, "Image skipped"; "symbol" => file_stem, "kind" => kind, "error" => %error);
+            Ok(())
+        }
+    }
 }
 
-pub(crate) fn extract_asset_packs() {
-    todo!()
-}
\ No newline at end of file
+/// Compares the footprint of the logic document against the furniture data rows using the asset pack.
+fn verify_logic(pack: &AssetPack, logger: &slog::Logger) {
+    let logic_path = pack
+        .extract_path()
+        .join(format!("{}_logic.xml", pack.asset_name));
+    let logic_document = match File::open(&logic_path) {
+        Ok(file) => logic::from_reader(BufReader::new(file)),
+        Err(_) => return,
+    };
+    let object_data = match logic_document {
+        Ok(object_data) => object_data,
+        Err(error) => {
+            slog::warn!(logger, "Logic document unreadable"; "path" => %logic_path.display(), "error" => %error);
+            return;
+        }
+    };
+    let dimensions = match object_data.dimensions() {
+        Some(dimensions) => dimensions,
+        None => return,
+    };
+
+    for item in pack.furniture.iter() {
+        if let FurnitureType::Room(ref room) = *item {
+            if room.x_dimension != dimensions.x || room.y_dimension != dimensions.y {
+                slog::warn!(logger, "Logic dimensions disagree with furniture data";
+                    "classname" => &room.class_name,
+                    "furnidata" => format!("{}x{}", room.x_dimension, room.y_dimension),
+                    "logic" => format!("{}x{}", dimensions.x, dimensions.y));
+            }
+        }
+    }
+}
+
+/// Reads the asset list and writes the `assets.json` manifest with the registration offset of every sprite.
+///
+/// Aliases don't own a bitmap, they're materialized as a copy of their source, flipped when requested. So
+/// every asset named by the visualization exists on disk.
+fn materialize_assets(
+    pack: &AssetPack,
+    report: &mut ExtractionReport,
+    logger: &slog::Logger,
+) -> Result<(), runtime::Error> {
+    let extract_path = pack.extract_path();
+    let assets_path = extract_path.join(format!("{}_assets.xml", pack.asset_name));
+    let asset_list = match File::open(&assets_path) {
+        Ok(file) => match assets::from_reader(BufReader::new(file)) {
+            Ok(asset_list) => asset_list,
+            Err(error) => {
+                slog::warn!(logger, "Asset list unreadable"; "path" => %assets_path.display(), "error" => %error);
+                return Ok(());
+            }
+        },
+        Err(_) => return Ok(()),
+    };
+
+    let mut manifest = assets::AssetManifest::default();
+    for asset in asset_list.items.iter() {
+        let file_name = format!("{}.png", asset.name);
+        let destination = extract_path.join(&file_name);
+        let source = asset_list.resolve_source(asset);
+
+        if source.name != asset.name && !destination.exists() {
+            let source_path = extract_path.join(format!("{}.png", source.name));
+            let source_data = match fs::read(&source_path) {
+                Ok(data) => data,
+                Err(_) => {
+                    slog::warn!(logger, "Alias without source image"; "asset" => &asset.name, "source" => &source.name);
+                    continue;
+                }
+            };
+
+            let materialized = bitmap::RgbaImage::decode_png(&source_data).and_then(|image| {
+                let image = match asset.is_flipped_horizontally() {
+                    true => image.flip_horizontal(),
+                    false => image,
+                };
+                let image = match asset.is_flipped_vertically() {
+                    true => image.flip_vertical(),
+                    false => image,
+                };
+                image.encode_png()
+            });
+            match materialized {
+                Ok(png_data) => write_asset(&destination, &png_data, report)?,
+                Err(error) => {
+                    slog::warn!(logger, "Alias skipped"; "asset" => &asset.name, "error" => %error);
+                    continue;
+                }
+            }
+        }
+
+        if !destination.exists() {
+            continue;
+        }
+        manifest.assets.push(assets::ExtractedAsset {
+            name: asset.name.clone(),
+            file: file_name,
+            x: asset.x,
+            y: asset.y,
+            source: asset.source.clone(),
+            flip_h: asset.is_flipped_horizontally(),
+            flip_v: asset.is_flipped_vertically(),
+        });
+    }
+
+    let manifest_file =
+        File::create(extract_path.join(assets::MANIFEST_FILE)).map_err(runtime::Error::Io)?;
+    serde_json::to_writer_pretty(manifest_file, &manifest)
+        .map_err(|error| runtime::Error::Io(error.into()))
+}
+
+/// Collects the extracted images, in manifest order when the asset list was available.
+fn load_sprites(
+    extract_path: &Path,
+    logger: &slog::Logger,
+) -> Result<Vec<atlas::Sprite>, runtime::Error> {
+    let manifest: Option<assets::AssetManifest> =
+        File::open(extract_path.join(assets::MANIFEST_FILE))
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok());
+    let entries: Vec<(String, String, i32, i32)> = match manifest {
+        Some(manifest) => manifest
+            .assets
+            .into_iter()
+            .map(|asset| (asset.name, asset.file, asset.x, asset.y))
+            .collect(),
+        None => {
+            let mut files: Vec<String> = fs::read_dir(extract_path)
+                .map_err(runtime::Error::Io)?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|file_name| file_name.ends_with(".png"))
+                .collect();
+            files.sort();
+            files
+                .into_iter()
+                .map(|file| (file.trim_end_matches(".png").to_string(), file, 0, 0))
+                .collect()
+        }
+    };
+
+    let mut sprites = Vec::with_capacity(entries.len());
+    for (name, file, offset_x, offset_y) in entries {
+        let data = fs::read(extract_path.join(&file)).map_err(runtime::Error::Io)?;
+        match bitmap::RgbaImage::decode_png(&data) {
+            Ok(image) => sprites.push(atlas::Sprite {
+                name,
+                image,
+                offset_x,
+                offset_y,
+            }),
+            Err(error) => {
+                slog::warn!(logger, "Image unreadable"; "file" => file, "error" => %error)
+            }
+        }
+    }
+    Ok(sprites)
+}
+
+/// Packs all images of the asset pack into `atlas/<asset name>_<n>.png` with a frame map next to each.
+fn write_atlases(
+    pack: &AssetPack,
+    options: &ExtractionOptions,
+    logger: &slog::Logger,
+) -> Result<(), runtime::Error> {
+    let extract_path = pack.extract_path();
+    let sprites = load_sprites(&extract_path, logger)?;
+    let (atlases, oversized) = atlas::pack(
+        &sprites,
+        &pack.asset_name,
+        options.atlas_max_size,
+        options.atlas_trim,
+    );
+    if !oversized.is_empty() {
+        slog::warn!(logger, "Images exceed the atlas size"; "images" => ?oversized);
+    }
+
+    // NOTE; Atlases of an earlier run are dropped, their count might differ.
+    let atlas_path = extract_path.join("atlas");
+    if atlas_path.exists() {
+        fs::remove_dir_all(&atlas_path).map_err(runtime::Error::Io)?;
+    }
+    fs::create_dir_all(&atlas_path).map_err(runtime::Error::Io)?;
+
+    for packed in atlases.iter() {
+        let image_path = atlas_path.join(&packed.frame_map.meta.image);
+        let png_data = packed.image.encode_png().map_err(runtime::Error::Image)?;
+        fs::write(&image_path, png_data).map_err(runtime::Error::Io)?;
+
+        let frame_map_file =
+            File::create(image_path.with_extension("json")).map_err(runtime::Error::Io)?;
+        serde_json::to_writer_pretty(frame_map_file, &packed.frame_map)
+            .map_err(|error| runtime::Error::Io(error.into()))?;
+    }
+
+    slog::debug!(logger, "Atlases written"; "count" => atlases.len(), "sprites" => sprites.len());
+    Ok(())
+}
+
+/// Converts the asset pack into `<asset name>.nitro` next to the SWF file.
+///
+/// NOTE; Aliases stay references towards their source, only images owning a bitmap go onto the spritesheet.
+fn write_nitro_bundle(
+    pack: &AssetPack,
+    options: &ExtractionOptions,
+    logger: &slog::Logger,
+) -> Result<(), runtime::Error> {
+    let extract_path = pack.extract_path();
+    let document_path = |kind: &str| extract_path.join(format!("{}_{}.xml", pack.asset_name, kind));
+
+    let asset_list =
+        furni_document::load_document(&document_path("assets"), assets::from_reader, logger)
+            .unwrap_or_default();
+    let index_data =
+        furni_document::load_document(&document_path("index"), index::from_reader, logger);
+    let object_data =
+        furni_document::load_document(&document_path("logic"), logic::from_reader, logger);
+    let visualizations = furni_document::load_document(
+        &document_path("visualization"),
+        visualization::from_reader,
+        logger,
+    )
+    .map(|data| data.graphics.visualizations)
+    .unwrap_or_default();
+
+    let mut sprites = Vec::new();
+    for asset in asset_list
+        .items
+        .iter()
+        .filter(|asset| asset.source.is_none())
+    {
+        let data = match fs::read(extract_path.join(format!("{}.png", asset.name))) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        match bitmap::RgbaImage::decode_png(&data) {
+            Ok(image) => sprites.push(atlas::Sprite {
+                name: nitro::frame_name(&pack.asset_name, &asset.name),
+                image,
+                offset_x: asset.x,
+                offset_y: asset.y,
+            }),
+            Err(error) => {
+                slog::warn!(logger, "Image unreadable"; "asset" => &asset.name, "error" => %error)
+            }
+        }
+    }
+
+    let (mut atlases, oversized) = atlas::pack(
+        &sprites,
+        &pack.asset_name,
+        options.atlas_max_size,
+        options.atlas_trim,
+    );
+    let bundle_path = pack.swf_path.with_extension("nitro");
+    // NOTE; Nitro bundles hold one spritesheet, an incomplete bundle is a failed extraction.
+    if !oversized.is_empty() || atlases.len() > 1 {
+        return Err(runtime::Error::InvalidBundle(
+            bundle_path,
+            format!(
+                "images need {} spritesheets of at most {} pixels, oversized: {:?}",
+                atlases.len(),
+                options.atlas_max_size,
+                oversized
+            ),
+        ));
+    }
+
+    let image_name = format!("{}.png", pack.asset_name);
+    let (spritesheet, frame_map) = match atlases.pop() {
+        Some(mut packed) => {
+            packed.frame_map.meta.image = image_name.clone();
+            (Some(packed.image), packed.frame_map)
+        }
+        None => (None, atlas::FrameMap::empty(&image_name)),
+    };
+
+    let asset_data = nitro::convert(
+        &pack.asset_name,
+        index_data.as_ref(),
+        &asset_list,
+        object_data.as_ref(),
+        &visualizations,
+        frame_map,
+    );
+    let mut bundle = nitro::NitroBundle::default();
+    bundle.files.push((
+        format!("{}.json", pack.asset_name),
+        serde_json::to_vec(&asset_data).map_err(|error| runtime::Error::Io(error.into()))?,
+    ));
+    if let Some(spritesheet) = spritesheet {
+        bundle.files.push((
+            image_name,
+            spritesheet.encode_png().map_err(runtime::Error::Image)?,
+        ));
+    }
+
+    fs::write(&bundle_path, bundle.to_bytes().map_err(runtime::Error::Io)?)
+        .map_err(runtime::Error::Io)?;
+    slog::debug!(logger, "Nitro bundle written"; "path" => %bundle_path.display());
+    Ok(())
+}
+
+/// Writes every binary data blob and bitmap of the asset pack into its extract folder.
+///
+/// Characters are resolved in two passes, symbols first, so the result doesn't depend on tag order.
+pub(crate) fn extract_asset_packs(
+    pack: &AssetPack,
+    options: &ExtractionOptions,
+    logger: &slog::Logger,
+) -> Result<ExtractionReport, runtime::Error> {
+    let logger = logger.new(slog::o!("asset" => pack.asset_name.clone()));
+    let swf_blob = fs::read(&pack.swf_path).map_err(runtime::Error::Io)?;
+    let swf_blob = swf_container::decompress(&swf_blob)
+        .map_err(|reason| runtime::Error::SwfParse(pack.swf_path.clone(), reason))?;
+    let swf_movie = read_swf(&swf_blob[..])
+        .map_err(|error| runtime::Error::SwfParse(pack.swf_path.clone(), error.to_string()))?;
+
+    let extract_path = pack.extract_path();
+    fs::create_dir_all(&extract_path).map_err(runtime::Error::Io)?;
+
+    let symbols = collect_symbols(&swf_movie.tags, &pack.asset_name);
+    let jpeg_tables = swf_movie.tags.iter().find_map(|tag| match tag {
+        Tag::JpegTables(tables) => Some(&tables[..]),
+        _ => None,
+    });
+    let mut report = ExtractionReport::default();
+    for tag in swf_movie.tags.iter() {
+        match tag {
+            Tag::DefineBinaryData { id, data } => {
+                let asset_id = *id as u16;
+                let file_stem = match symbols.get(&asset_id) {
+                    Some(name) => name,
+                    None => {
+                        report.unresolved.push((asset_id, "DefineBinaryData"));
+                        continue;
+                    }
+                };
+                let destination = extract_path.join(format!("{}.xml", file_stem));
+                write_asset(&destination, data, &mut report)?;
+            }
+            Tag::DefineBitsLossless(lossless) => write_image(
+                &extract_path,
+                &symbols,
+                (lossless.id as u16, "DefineBitsLossless"),
+                &mut report,
+                &logger,
+                || bitmap::decode_lossless(lossless),
+            )?,
+            Tag::DefineBits { id, jpeg_data } => write_image(
+                &extract_path,
+                &symbols,
+                (*id as u16, "DefineBits"),
+                &mut report,
+                &logger,
+                || bitmap::decode_jpeg(jpeg_tables, jpeg_data, None),
+            )?,
+            Tag::DefineBitsJpeg2 { id, jpeg_data } => write_image(
+                &extract_path,
+                &symbols,
+                (*id as u16, "DefineBitsJpeg2"),
+                &mut report,
+                &logger,
+                || bitmap::decode_jpeg(None, jpeg_data, None),
+            )?,
+            // NOTE; DefineBitsJpeg4 is parsed into the same structure, with version 4.
+            Tag::DefineBitsJpeg3(jpeg) => write_image(
+                &extract_path,
+                &symbols,
+                (jpeg.id as u16, "DefineBitsJpeg3"),
+                &mut report,
+                &logger,
+                || bitmap::decode_jpeg(None, &jpeg.data, Some(&jpeg.alpha_data[..])),
+            )?,
+            _ => continue,
+        }
+    }
+
+    verify_logic(pack, &logger);
+    materialize_assets(pack, &mut report, &logger)?;
+    furni_document::write_document(pack, &logger)?;
+    match options.format {
+        OutputFormat::Loose => {}
+        OutputFormat::Atlas => write_atlases(pack, options, &logger)?,
+        OutputFormat::Nitro => write_nitro_bundle(pack, options, &logger)?,
+    }
+
+    if !report.unresolved.is_empty() {
+        slog::warn!(logger, "Characters without symbol name";
+            "characters" => ?report.unresolved);
+    }
+    slog::debug!(logger, "Asset pack extracted";
+        "written" => report.written, "skipped" => report.skipped);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Reply, TestServer};
+
+    /// Smallest payload passing validation, an uncompressed header with a 4 byte body.
+    const MOVIE: [u8; 12] = [b'F', b'W', b'S', 10, 12, 0, 0, 0, 0, 0, 0, 0];
+
+    fn pack(server: &TestServer, data_path: &Path, asset_name: &str, revision: u32) -> AssetPack {
+        let runtime = RuntimeData::new(data_path.to_path_buf(), String::from("test"));
+        AssetPack {
+            swf_path: runtime
+                .get_asset_path()
+                .join(revision.to_string())
+                .join(format!("{}.swf", asset_name)),
+            runtime,
+            hotel: String::from("test"),
+            asset_name: String::from(asset_name),
+            revision,
+            url: server.url(&format!("/{}/{}.swf", revision, asset_name)),
+            furniture: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn transient_failures_are_retried() {
+        let server = TestServer::start(|path, attempt| match (path, attempt) {
+            ("/1/server_error.swf", 0) | ("/1/server_error.swf", 1) => Reply::status(503),
+            ("/1/stalled.swf", 0) => Reply::Stall(Duration::from_secs(2)),
+            ("/1/truncated.swf", 0) => Reply::Truncated {
+                length: 64,
+                body: MOVIE[..6].to_vec(),
+            },
+            ("/1/missing.swf", _) => Reply::status(404),
+            ("/1/broken.swf", _) => Reply::status(500),
+            _ => Reply::ok(&MOVIE[..]),
+        });
+        let data_path = tempfile::tempdir().unwrap();
+        let client = Client::builder()
+            .timeout(Duration::from_millis(250))
+            .build()
+            .unwrap();
+        let limiter = HostLimiter::new(2);
+        let policy = RetryPolicy {
+            attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+
+        let mut report = DownloadReport::default();
+        let assets = [
+            ("server_error", 1),
+            ("stalled", 1),
+            ("truncated", 1),
+            ("missing", 1),
+            ("broken", 1),
+            ("unversioned", 0),
+        ];
+        for &(asset_name, revision) in assets.iter() {
+            let pack = pack(&server, data_path.path(), asset_name, revision);
+            let status = download_asset_pack(
+                &client,
+                &pack,
+                &limiter,
+                &policy,
+                Duration::from_secs(0),
+                &logger,
+            );
+            if status == DownloadStatus::Ok {
+                assert_eq!(fs::read(&pack.swf_path).unwrap(), MOVIE);
+            }
+            report.record(status);
+        }
+
+        assert_eq!(server.request_count("/1/server_error.swf"), 3);
+        assert_eq!(server.request_count("/1/stalled.swf"), 2);
+        assert_eq!(server.request_count("/1/truncated.swf"), 2);
+        // NOTE; Client errors are permanent, server errors are retried until the attempts run out.
+        assert_eq!(server.request_count("/1/missing.swf"), 1);
+        assert_eq!(server.request_count("/1/broken.swf"), 3);
+        assert_eq!(server.request_count("/0/unversioned.swf"), 0);
+        assert_eq!(
+            report,
+            DownloadReport {
+                ok: 3,
+                skipped: 1,
+                failed: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_maximum() {
+        let policy = RetryPolicy {
+            attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(3), Duration::from_millis(500));
+        assert_eq!(policy.delay(40), Duration::from_millis(500));
+    }
+
+    fn image(width: u32, height: u32, pixels: &[[u8; 4]]) -> bitmap::RgbaImage {
+        bitmap::RgbaImage {
+            width,
+            height,
+            pixels: pixels.iter().flatten().cloned().collect(),
+        }
+    }
+
+    fn read_manifest(path: &Path) -> assets::AssetManifest {
+        let mut manifest: assets::AssetManifest =
+            serde_json::from_slice(&fs::read(path.join(assets::MANIFEST_FILE)).unwrap()).unwrap();
+        manifest.assets.sort_by(|a, b| a.name.cmp(&b.name));
+        manifest
+    }
+
+    #[test]
+    fn sliced_nitro_bundle_matches_loose_images() {
+        let data_path = tempfile::tempdir().unwrap();
+        let runtime = RuntimeData::new(data_path.path().to_path_buf(), String::from("test"));
+        let pack = AssetPack {
+            swf_path: runtime.get_asset_path().join("1").join("test.swf"),
+            runtime,
+            hotel: String::from("test"),
+            asset_name: String::from("test"),
+            revision: 1,
+            url: Url::parse("http://localhost/1/test.swf").unwrap(),
+            furniture: Vec::new(),
+        };
+        let extract_path = pack.extract_path();
+        fs::create_dir_all(&extract_path).unwrap();
+
+        // NOTE; The transparent column is trimmed off the atlas and restored by slicing.
+        let (red, blue, clear) = ([255, 0, 0, 255], [0, 0, 255, 128], [0, 0, 0, 0]);
+        let images = [
+            (
+                "test_64_a_0",
+                image(3, 2, &[clear, red, blue, clear, blue, red]),
+            ),
+            ("test_64_b_0", image(2, 2, &[blue, blue, red, red])),
+        ];
+        for (name, image) in images.iter() {
+            let destination = extract_path.join(format!("{}.png", name));
+            fs::write(destination, image.encode_png().unwrap()).unwrap();
+        }
+        fs::write(
+            extract_path.join("test_assets.xml"),
+            r#"<assets>
+                <asset name="test_64_a_0" x="-1" y="5"/>
+                <asset name="test_64_a_2" source="test_64_a_0" x="4" y="5" flipH="1"/>
+                <asset name="test_64_b_0" x="0" y="-2"/>
+                <asset name="test_64_b_2" source="test_64_b_0" flipV="1"/>
+            </assets>"#,
+        )
+        .unwrap();
+
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let mut report = ExtractionReport::default();
+        materialize_assets(&pack, &mut report, &logger).unwrap();
+        let options = ExtractionOptions {
+            format: OutputFormat::Nitro,
+            atlas_trim: true,
+            atlas_max_size: 256,
+        };
+        write_nitro_bundle(&pack, &options, &logger).unwrap();
+
+        let bundle_path = pack.swf_path.with_extension("nitro");
+        let data = fs::read(&bundle_path).unwrap();
+        let bundle = nitro::NitroBundle::from_bytes(&data).unwrap();
+        assert_eq!(bundle.to_bytes().unwrap(), data);
+
+        let unpacked = data_path.path().join("unpacked");
+        nitro::unpack(&bundle_path, &unpacked, true, &logger).unwrap();
+        let sprites_path = unpacked.join("sprites");
+
+        let loose = read_manifest(&extract_path);
+        let sliced = read_manifest(&sprites_path);
+        assert_eq!(loose.assets.len(), 4);
+        assert_eq!(sliced, loose);
+        for asset in loose.assets.iter() {
+            let decode = |path: &Path| {
+                bitmap::RgbaImage::decode_png(&fs::read(path.join(&asset.file)).unwrap()).unwrap()
+            };
+            assert_eq!(
+                decode(&sprites_path),
+                decode(&extract_path),
+                "{}",
+                asset.name
+            );
+        }
+
+        let mut files: Vec<String> = fs::read_dir(&sprites_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "assets.json",
+                "test_64_a_0.png",
+                "test_64_a_2.png",
+                "test_64_b_0.png",
+                "test_64_b_2.png",
+            ]
+        );
+    }
+}
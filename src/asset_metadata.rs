@@ -1,12 +1,336 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::Duration;
 
-pub(crate) fn prepare_folders() {
-    todo!()
+use reqwest::{blocking::Client, StatusCode, Url};
+
+use crate::cache::{self, CacheRecord};
+use crate::cli::FurnidataFormat;
+use crate::furnidata::{self, FurnitureData};
+use crate::runtime::{self, RuntimeData};
+
+/// Endpoint which redirects to the current, hashed, furniture data document in XML form.
+const FURNIDATA_XML_ENDPOINT: &str = "gamedata/furnidata_xml/1";
+/// Endpoint which redirects to the current, hashed, furniture data document in JSON form.
+const FURNIDATA_JSON_ENDPOINT: &str = "gamedata/furnidata_json/1";
+/// File name of the XML furniture data document inside the revision folder.
+pub(crate) const FURNIDATA_XML_FILE: &str = "furnidata.xml";
+/// File name of the JSON furniture data document inside the revision folder.
+pub(crate) const FURNIDATA_JSON_FILE: &str = "furnidata.json";
+/// Endpoint which redirects to the current external variables of the hotel.
+const EXTERNAL_VARIABLES_ENDPOINT: &str = "gamedata/external_variables/1";
+/// File name of the external variables inside the revision folder.
+pub(crate) const EXTERNAL_VARIABLES_FILE: &str = "external_variables.txt";
+
+/// Extracts the revision from the redirect target of the furniture data endpoint.
+///
+/// The endpoint `/gamedata/furnidata_xml/1` redirects towards `/gamedata/furnidata_xml/<hash>`, the hash
+/// changes each time the hotel publishes new furniture.
+pub(crate) fn revision_from_target(endpoint: &Url, target: &Url) -> Result<String, runtime::Error> {
+    if endpoint == target {
+        return Err(runtime::Error::MissingRevision(endpoint.clone()));
+    }
+
+    target
+        .path_segments()
+        .and_then(|segments| segments.filter(|segment| !segment.is_empty()).last())
+        .map(String::from)
+        .ok_or_else(|| runtime::Error::MissingRevision(target.clone()))
+}
+
+fn furnidata_endpoint(gamedata_base: &Url, endpoint_path: &str) -> Result<Url, runtime::Error> {
+    gamedata_base
+        .join(endpoint_path)
+        .map_err(|_| runtime::Error::InvalidUrl(format!("{}{}", gamedata_base, endpoint_path)))
+}
+
+pub(crate) fn prepare_folders(runtime: &RuntimeData) -> Result<(), runtime::Error> {
+    fs::create_dir_all(runtime.get_data_path()).map_err(runtime::Error::Io)
+}
+
+/// Downloads the furniture data of the provided hotel and stores it inside the folder of its revision.
+///
+/// With [`FurnidataFormat::Auto`] the XML document is preferred, the JSON document is used when the hotel
+/// doesn't publish XML.
+pub(crate) fn download_index_data(
+    client: &Client,
+    gamedata_base: &Url,
+    base_data_path: PathBuf,
+    format: FurnidataFormat,
+    cache_time: Duration,
+    logger: &slog::Logger,
+) -> Result<RuntimeData, runtime::Error> {
+    let download = |endpoint_path: &str, file_name: &str| {
+        download_furnidata(
+            client,
+            gamedata_base,
+            endpoint_path,
+            file_name,
+            base_data_path.clone(),
+            cache_time,
+            logger,
+        )
+    };
+
+    let runtime = match format {
+        FurnidataFormat::Xml => download(FURNIDATA_XML_ENDPOINT, FURNIDATA_XML_FILE),
+        FurnidataFormat::Json => download(FURNIDATA_JSON_ENDPOINT, FURNIDATA_JSON_FILE),
+        FurnidataFormat::Auto => {
+            download(FURNIDATA_XML_ENDPOINT, FURNIDATA_XML_FILE).or_else(|error| {
+                slog::warn!(logger, "XML furniture data unavailable, falling back to JSON"; "error" => %error);
+                download(FURNIDATA_JSON_ENDPOINT, FURNIDATA_JSON_FILE)
+            })
+        }
+    }?;
+
+    // NOTE; External variables are informative, the extraction doesn't depend on them.
+    if let Err(error) =
+        download_external_variables(client, gamedata_base, &runtime, cache_time, logger)
+    {
+        slog::warn!(logger, "External variables unavailable"; "error" => %error);
+    }
+
+    Ok(runtime)
+}
+
+fn download_furnidata(
+    client: &Client,
+    gamedata_base: &Url,
+    endpoint_path: &str,
+    file_name: &str,
+    base_data_path: PathBuf,
+    cache_time: Duration,
+    logger: &slog::Logger,
+) -> Result<RuntimeData, runtime::Error> {
+    let endpoint = furnidata_endpoint(gamedata_base, endpoint_path)?;
+
+    // NOTE; The record of the endpoint lives outside the revision folders and points at the last known
+    // revision. It's only usable while that revision folder still holds the document.
+    let record_file = base_data_path.join(file_name);
+    let cached = CacheRecord::load(&record_file).and_then(|record| {
+        let target = Url::parse(&record.target).ok()?;
+        let revision = revision_from_target(&endpoint, &target).ok()?;
+        let runtime = RuntimeData::new(base_data_path.clone(), revision);
+        match runtime.get_data_path().join(file_name).is_file() {
+            true => Some((record, runtime)),
+            false => None,
+        }
+    });
+
+    let mut request = client.get(endpoint.clone());
+    if let Some((ref record, ref runtime)) = cached {
+        if record.is_fresh(cache_time) {
+            slog::debug!(logger, "Furniture data reused from cache"; "revision" => runtime.get_revision());
+            return Ok(runtime.clone());
+        }
+        request = record.revalidate(request);
+    }
+
+    // NOTE; The client follows redirects, the final url of the response holds the revision hash.
+    let mut response = request
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(runtime::Error::HttpClient)?;
+    let revision = revision_from_target(&endpoint, response.url())?;
+    slog::debug!(logger, "Discovered furniture data revision"; "url" => %response.url(), "revision" => &revision);
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return match cached {
+            Some((mut record, runtime)) if runtime.get_revision() == revision => {
+                record.touch();
+                record.store(&record_file)?;
+                slog::debug!(logger, "Furniture data revalidated"; "revision" => &revision);
+                Ok(runtime)
+            }
+            _ => Err(runtime::Error::EmptyBody(endpoint)),
+        };
+    }
+
+    let runtime = RuntimeData::new(base_data_path, revision);
+    prepare_folders(&runtime)?;
+
+    let record = CacheRecord::from_response(&response);
+    let furnidata_path = runtime.get_data_path().join(file_name);
+    let mut furnidata_file = File::create(&furnidata_path).map_err(runtime::Error::Io)?;
+    if response
+        .copy_to(&mut furnidata_file)
+        .map_err(runtime::Error::HttpClient)?
+        == 0
+    {
+        return Err(runtime::Error::EmptyBody(endpoint));
+    }
+    record.store(&record_file)?;
+
+    slog::info!(logger, "Furniture data downloaded"; "path" => %furnidata_path.display());
+    Ok(runtime)
+}
+
+fn download_external_variables(
+    client: &Client,
+    gamedata_base: &Url,
+    runtime: &RuntimeData,
+    cache_time: Duration,
+    logger: &slog::Logger,
+) -> Result<(), runtime::Error> {
+    let endpoint = gamedata_base
+        .join(EXTERNAL_VARIABLES_ENDPOINT)
+        .map_err(|_| {
+            runtime::Error::InvalidUrl(format!("{}{}", gamedata_base, EXTERNAL_VARIABLES_ENDPOINT))
+        })?;
+    let destination = runtime.get_data_path().join(EXTERNAL_VARIABLES_FILE);
+
+    let outcome = cache::fetch(client, &endpoint, &destination, cache_time, logger)?;
+    slog::debug!(logger, "External variables"; "outcome" => ?outcome);
+    Ok(())
 }
 
-pub(crate) fn download_index_data() {
-    todo!()
+/// Revision of the furniture data fetched by the last download, without touching the network.
+///
+/// NOTE; The XML document is preferred, like [`load_index_data`] does.
+pub(crate) fn cached_index_data(
+    gamedata_base: &Url,
+    base_data_path: PathBuf,
+) -> Result<RuntimeData, runtime::Error> {
+    let documents = [
+        (FURNIDATA_XML_ENDPOINT, FURNIDATA_XML_FILE),
+        (FURNIDATA_JSON_ENDPOINT, FURNIDATA_JSON_FILE),
+    ];
+    documents
+        .iter()
+        .filter_map(|&(endpoint_path, file_name)| {
+            let endpoint = furnidata_endpoint(gamedata_base, endpoint_path).ok()?;
+            let record = CacheRecord::load(&base_data_path.join(file_name))?;
+            let target = Url::parse(&record.target).ok()?;
+            let revision = revision_from_target(&endpoint, &target).ok()?;
+            let runtime = RuntimeData::new(base_data_path.clone(), revision);
+            match runtime.get_data_path().join(file_name).is_file() {
+                true => Some(runtime),
+                false => None,
+            }
+        })
+        .next()
+        .ok_or_else(|| runtime::Error::NoCachedRevision(base_data_path.clone()))
+}
 
-    // Furniture data xml (/gamedata/furnidata_xml/1 -> REDIR)
-    // Also extract the revision!
+/// Checks whether furniture data of the revision was downloaded during an earlier run.
+pub(crate) fn has_index_data(runtime: &RuntimeData) -> bool {
+    let data_path = runtime.get_data_path();
+    data_path.join(FURNIDATA_XML_FILE).is_file() || data_path.join(FURNIDATA_JSON_FILE).is_file()
 }
 
+/// Parses the furniture data document stored inside the revision folder.
+///
+/// NOTE; The XML document is used when both forms are present.
+pub(crate) fn load_index_data(runtime: &RuntimeData) -> Result<FurnitureData, runtime::Error> {
+    let data_path = runtime.get_data_path();
+    let xml_path = data_path.join(FURNIDATA_XML_FILE);
+    if xml_path.exists() {
+        let reader = BufReader::new(File::open(&xml_path).map_err(runtime::Error::Io)?);
+        return furnidata::from_xml_reader(reader);
+    }
+
+    let json_path = data_path.join(FURNIDATA_JSON_FILE);
+    let reader = BufReader::new(File::open(&json_path).map_err(runtime::Error::Io)?);
+    furnidata::from_json_reader(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::test_server::{Reply, TestServer};
+
+    const FURNIDATA_XML: &str =
+        "<furnidata><roomitemtypes></roomitemtypes><wallitemtypes></wallitemtypes></furnidata>";
+
+    fn download(server: &TestServer, data_path: &Path) -> Result<RuntimeData, runtime::Error> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        download_index_data(
+            &Client::new(),
+            &server.url("/"),
+            data_path.to_path_buf(),
+            FurnidataFormat::Xml,
+            Duration::from_secs(0),
+            &logger,
+        )
+    }
+
+    #[test]
+    fn revision_follows_redirect() {
+        let server = TestServer::start(|path, _| match path {
+            "/gamedata/furnidata_xml/1" => Reply::redirect("/gamedata/furnidata_xml/3f2a9c"),
+            "/gamedata/furnidata_xml/3f2a9c" => Reply::ok(FURNIDATA_XML),
+            _ => Reply::status(404),
+        });
+        let data_path = tempfile::tempdir().unwrap();
+
+        let runtime = download(&server, data_path.path()).unwrap();
+        assert_eq!(runtime.get_revision(), "3f2a9c");
+        let stored = fs::read_to_string(runtime.get_data_path().join(FURNIDATA_XML_FILE)).unwrap();
+        assert_eq!(stored, FURNIDATA_XML);
+        assert_eq!(
+            server.requests()[..2],
+            [
+                "/gamedata/furnidata_xml/1".to_string(),
+                "/gamedata/furnidata_xml/3f2a9c".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_redirect_has_no_revision() {
+        let server = TestServer::start(|path, _| match path {
+            "/gamedata/furnidata_xml/1" => Reply::ok(FURNIDATA_XML),
+            _ => Reply::status(404),
+        });
+        let data_path = tempfile::tempdir().unwrap();
+
+        match download(&server, data_path.path()) {
+            Err(runtime::Error::MissingRevision(url)) => {
+                assert_eq!(url.path(), "/gamedata/furnidata_xml/1")
+            }
+            other => panic!("Expected missing revision, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn empty_body_is_rejected() {
+        let server = TestServer::start(|path, _| match path {
+            "/gamedata/furnidata_xml/1" => Reply::redirect("/gamedata/furnidata_xml/3f2a9c"),
+            "/gamedata/furnidata_xml/3f2a9c" => Reply::ok(""),
+            _ => Reply::status(404),
+        });
+        let data_path = tempfile::tempdir().unwrap();
+
+        match download(&server, data_path.path()) {
+            Err(runtime::Error::EmptyBody(_)) => {}
+            other => panic!("Expected empty body, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn revision_is_last_path_segment() {
+        let endpoint = Url::parse("https://www.habbo.com/gamedata/furnidata_xml/1").unwrap();
+        let target = Url::parse("https://www.habbo.com/gamedata/furnidata_xml/abc/").unwrap();
+        assert_eq!(revision_from_target(&endpoint, &target).unwrap(), "abc");
+        assert!(revision_from_target(&endpoint, &endpoint).is_err());
+    }
+
+    #[test]
+    fn cached_revision_is_found_offline() {
+        let server = TestServer::start(|path, _| match path {
+            "/gamedata/furnidata_xml/1" => Reply::redirect("/gamedata/furnidata_xml/3f2a9c"),
+            "/gamedata/furnidata_xml/3f2a9c" => Reply::ok(FURNIDATA_XML),
+            _ => Reply::status(404),
+        });
+        let data_path = tempfile::tempdir().unwrap();
+        let gamedata_base = server.url("/");
+
+        assert!(cached_index_data(&gamedata_base, data_path.path().to_path_buf()).is_err());
+        download(&server, data_path.path()).unwrap();
+        let runtime = cached_index_data(&gamedata_base, data_path.path().to_path_buf()).unwrap();
+        assert_eq!(runtime.get_revision(), "3f2a9c");
+    }
+}
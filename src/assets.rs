@@ -0,0 +1,100 @@
+//! Typed model of the `<asset name>_assets.xml` document embedded in each furniture asset pack, and of the
+//! `assets.json` manifest the extractor writes next to the extracted images.
+
+use std::io::BufRead;
+
+use quick_xml::de::DeError;
+use serde::{Deserialize, Serialize};
+
+/// File name of the manifest inside the extract folder of an asset pack.
+pub const MANIFEST_FILE: &str = "assets.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "assets")]
+pub struct AssetList {
+    #[serde(rename = "asset", default)]
+    pub items: Vec<Asset>,
+}
+
+/// One sprite, either backed by its own bitmap or an alias of another sprite.
+///
+/// NOTE; `x` and `y` define the registration point, the sprite is drawn at minus that offset.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub source: Option<String>,
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+    #[serde(rename = "flipH")]
+    pub flip_h: Option<bool>,
+    #[serde(rename = "flipV")]
+    pub flip_v: Option<bool>,
+    #[serde(rename = "usesPalette")]
+    pub uses_palette: Option<bool>,
+}
+
+/// Entry of the `assets.json` manifest, every entry has an image file on disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractedAsset {
+    pub name: String,
+    /// File name of the image, relative to the extract folder.
+    pub file: String,
+    pub x: i32,
+    pub y: i32,
+    /// Asset the image was copied from, for aliases.
+    pub source: Option<String>,
+    pub flip_h: bool,
+    pub flip_v: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssetManifest {
+    pub assets: Vec<ExtractedAsset>,
+}
+
+impl Asset {
+    pub fn is_flipped_horizontally(&self) -> bool {
+        self.flip_h.unwrap_or(false)
+    }
+
+    pub fn is_flipped_vertically(&self) -> bool {
+        self.flip_v.unwrap_or(false)
+    }
+}
+
+impl AssetList {
+    pub fn get(&self, name: &str) -> Option<&Asset> {
+        self.items.iter().find(|asset| asset.name == name)
+    }
+
+    /// Follows the chain of aliases towards the asset which owns a bitmap.
+    ///
+    /// NOTE; Chains are cut off after a few steps to protect against cyclic aliases.
+    pub fn resolve_source<'a>(&'a self, asset: &'a Asset) -> &'a Asset {
+        let mut current = asset;
+        for _ in 0..8 {
+            match current.source.as_ref().and_then(|source| self.get(source)) {
+                Some(source) if source.name != current.name => current = source,
+                _ => break,
+            }
+        }
+        current
+    }
+}
+
+impl AssetManifest {
+    pub fn get(&self, name: &str) -> Option<&ExtractedAsset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+pub fn from_reader<R: BufRead>(reader: R) -> Result<AssetList, DeError> {
+    quick_xml::de::from_reader(reader)
+}
+
+pub fn from_str(document: &str) -> Result<AssetList, DeError> {
+    quick_xml::de::from_str(document)
+}
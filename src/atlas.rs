@@ -0,0 +1,559 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bitmap::RgbaImage;
+
+/// Transparent gap between sprites, avoids bleeding when the atlas is sampled with filtering.
+const PADDING: u32 = 1;
+/// Smallest atlas edge that is tried.
+const MIN_SIZE: u32 = 32;
+/// Largest atlas edge that can be requested, one atlas of this size takes 1 GiB of pixels.
+pub(crate) const MAX_SIZE: u32 = 16384;
+
+/// Image to place on an atlas, with its registration offset from the asset list.
+#[derive(Debug, Clone)]
+pub(crate) struct Sprite {
+    pub name: String,
+    pub image: RgbaImage,
+    pub offset_x: i32,
+    pub offset_y: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Size {
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub(crate) struct Point<T> {
+    pub x: T,
+    pub y: T,
+}
+
+/// Frame entry in the TexturePacker hash format, as read by Pixi.
+///
+/// NOTE; `offset` holds the untouched registration point of the asset list, `anchor` expresses the same
+/// point relative to the source size.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Frame {
+    pub frame: Rect,
+    pub rotated: bool,
+    pub trimmed: bool,
+    pub sprite_source_size: Rect,
+    pub source_size: Size,
+    pub anchor: Point<f32>,
+    pub offset: Point<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Meta {
+    pub app: &'static str,
+    pub version: &'static str,
+    pub image: String,
+    pub format: &'static str,
+    pub size: Size,
+    pub scale: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct FrameMap {
+    pub frames: BTreeMap<String, Frame>,
+    pub meta: Meta,
+}
+
+impl FrameMap {
+    /// Frame map without frames, for asset packs without images.
+    pub fn empty(image: &str) -> Self {
+        FrameMap {
+            frames: BTreeMap::new(),
+            meta: Meta {
+                app: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+                image: String::from(image),
+                format: "RGBA8888",
+                size: Size { w: 0, h: 0 },
+                scale: "1",
+            },
+        }
+    }
+}
+
+/// One packed atlas image together with its frame map.
+#[derive(Debug, Clone)]
+pub(crate) struct Atlas {
+    pub image: RgbaImage,
+    pub frame_map: FrameMap,
+}
+
+fn intersects(a: &Rect, b: &Rect) -> bool {
+    a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+}
+
+fn contains(outer: &Rect, inner: &Rect) -> bool {
+    inner.x >= outer.x
+        && inner.y >= outer.y
+        && inner.x + inner.w <= outer.x + outer.w
+        && inner.y + inner.h <= outer.y + outer.h
+}
+
+/// Max-rects bin using the best short side fit heuristic.
+struct MaxRectsBin {
+    free: Vec<Rect>,
+}
+
+impl MaxRectsBin {
+    fn new(width: u32, height: u32) -> Self {
+        MaxRectsBin {
+            free: vec![Rect {
+                x: 0,
+                y: 0,
+                w: width,
+                h: height,
+            }],
+        }
+    }
+
+    fn insert(&mut self, width: u32, height: u32) -> Option<Rect> {
+        // NOTE; Ties are broken on position, so placement is deterministic.
+        let placement = self
+            .free
+            .iter()
+            .filter(|free| free.w >= width && free.h >= height)
+            .map(|free| {
+                let leftover_w = free.w - width;
+                let leftover_h = free.h - height;
+                let short_side = std::cmp::min(leftover_w, leftover_h);
+                let long_side = std::cmp::max(leftover_w, leftover_h);
+                (short_side, long_side, free.y, free.x)
+            })
+            .min()?;
+
+        let used = Rect {
+            x: placement.3,
+            y: placement.2,
+            w: width,
+            h: height,
+        };
+        self.split(&used);
+        Some(used)
+    }
+
+    fn split(&mut self, used: &Rect) {
+        let mut free = Vec::with_capacity(self.free.len() * 2);
+        for rect in self.free.drain(..) {
+            if !intersects(&rect, used) {
+                free.push(rect);
+                continue;
+            }
+            if used.x > rect.x {
+                free.push(Rect {
+                    x: rect.x,
+                    y: rect.y,
+                    w: used.x - rect.x,
+                    h: rect.h,
+                });
+            }
+            if used.x + used.w < rect.x + rect.w {
+                let x = used.x + used.w;
+                free.push(Rect {
+                    x,
+                    y: rect.y,
+                    w: rect.x + rect.w - x,
+                    h: rect.h,
+                });
+            }
+            if used.y > rect.y {
+                free.push(Rect {
+                    x: rect.x,
+                    y: rect.y,
+                    w: rect.w,
+                    h: used.y - rect.y,
+                });
+            }
+            if used.y + used.h < rect.y + rect.h {
+                let y = used.y + used.h;
+                free.push(Rect {
+                    x: rect.x,
+                    y,
+                    w: rect.w,
+                    h: rect.y + rect.h - y,
+                });
+            }
+        }
+
+        // NOTE; Drop rectangles enclosed by another, of duplicates only the first is kept.
+        let mut pruned: Vec<Rect> = Vec::with_capacity(free.len());
+        for (idx, rect) in free.iter().enumerate() {
+            let enclosed = free.iter().enumerate().any(|(other_idx, other)| {
+                other_idx != idx && contains(other, rect) && (other != rect || other_idx < idx)
+            });
+            if !enclosed {
+                pruned.push(*rect);
+            }
+        }
+        self.free = pruned;
+    }
+}
+
+/// Bounding box of the pixels which aren't fully transparent.
+///
+/// NOTE; A fully transparent image keeps a single pixel so it still receives a frame.
+fn trim_bounds(image: &RgbaImage) -> Rect {
+    let width = image.width as usize;
+    let (mut min_x, mut min_y, mut max_x, mut max_y) =
+        (usize::max_value(), usize::max_value(), 0, 0);
+    for (idx, pixel) in image.pixels.chunks(4).enumerate() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        let (x, y) = (idx % width, idx / width);
+        min_x = std::cmp::min(min_x, x);
+        min_y = std::cmp::min(min_y, y);
+        max_x = std::cmp::max(max_x, x);
+        max_y = std::cmp::max(max_y, y);
+    }
+
+    match min_x == usize::max_value() {
+        true => Rect {
+            x: 0,
+            y: 0,
+            w: 1,
+            h: 1,
+        },
+        false => Rect {
+            x: min_x as u32,
+            y: min_y as u32,
+            w: (max_x - min_x + 1) as u32,
+            h: (max_y - min_y + 1) as u32,
+        },
+    }
+}
+
+fn power_of_two_sizes(max_size: u32) -> Vec<(u32, u32)> {
+    let mut edges = vec![];
+    let mut edge = MIN_SIZE;
+    while edge <= max_size {
+        edges.push(edge);
+        edge *= 2;
+    }
+
+    let mut sizes: Vec<(u32, u32)> = edges
+        .iter()
+        .flat_map(|&w| edges.iter().map(move |&h| (w, h)))
+        .filter(|&(w, h)| w >= h && w <= h * 2)
+        .collect();
+    // NOTE; Smallest area first, wider before taller.
+    sizes.sort_by_key(|&(w, h)| (w * h, std::cmp::Reverse(w)));
+    sizes
+}
+
+/// Places as many of the pending sprites on a bin of the given size, returns their positions.
+fn place(pending: &[(usize, Rect)], width: u32, height: u32) -> Vec<(usize, Rect)> {
+    let mut bin = MaxRectsBin::new(width, height);
+    pending
+        .iter()
+        .filter_map(|&(idx, bounds)| {
+            bin.insert(bounds.w + PADDING, bounds.h + PADDING)
+                .map(|rect| {
+                    (
+                        idx,
+                        Rect {
+                            x: rect.x,
+                            y: rect.y,
+                            w: bounds.w,
+                            h: bounds.h,
+                        },
+                    )
+                })
+        })
+        .collect()
+}
+
+fn blit(target: &mut RgbaImage, source: &RgbaImage, source_rect: &Rect, x: u32, y: u32) {
+    let row_length = source_rect.w as usize * 4;
+    for row in 0..source_rect.h as usize {
+        let source_start =
+            ((source_rect.y as usize + row) * source.width as usize + source_rect.x as usize) * 4;
+        let target_start = ((y as usize + row) * target.width as usize + x as usize) * 4;
+        target.pixels[target_start..target_start + row_length]
+            .copy_from_slice(&source.pixels[source_start..source_start + row_length]);
+    }
+}
+
+/// Packs the sprites onto one or more power of two atlases, no larger than `max_size` on each edge.
+///
+/// The result only depends on the sprites and options, sprites are ordered by size and name before packing.
+/// Sprites that exceed the maximum size are returned by name.
+pub(crate) fn pack(
+    sprites: &[Sprite],
+    image_stem: &str,
+    max_size: u32,
+    trim: bool,
+) -> (Vec<Atlas>, Vec<String>) {
+    let mut oversized = Vec::new();
+    let mut pending: Vec<(usize, Rect)> = Vec::new();
+    for (idx, sprite) in sprites.iter().enumerate() {
+        let bounds = match trim {
+            true => trim_bounds(&sprite.image),
+            false => Rect {
+                x: 0,
+                y: 0,
+                w: sprite.image.width,
+                h: sprite.image.height,
+            },
+        };
+        if bounds.w == 0 || bounds.h == 0 {
+            continue;
+        }
+        if bounds.w + PADDING > max_size || bounds.h + PADDING > max_size {
+            oversized.push(sprite.name.clone());
+            continue;
+        }
+        pending.push((idx, bounds));
+    }
+    pending.sort_by(|a, b| {
+        (b.1.h, b.1.w)
+            .cmp(&(a.1.h, a.1.w))
+            .then_with(|| sprites[a.0].name.cmp(&sprites[b.0].name))
+    });
+
+    let sizes = power_of_two_sizes(max_size);
+    let mut atlases = Vec::new();
+    while !pending.is_empty() {
+        // NOTE; The smallest size holding every pending sprite wins, otherwise the largest size is filled.
+        let (width, height, placed) = sizes
+            .iter()
+            .map(|&(w, h)| (w, h, place(&pending, w, h)))
+            .find(|(_, _, placed)| placed.len() == pending.len())
+            .unwrap_or_else(|| (max_size, max_size, place(&pending, max_size, max_size)));
+        if placed.is_empty() {
+            break;
+        }
+
+        let image_name = format!("{}_{}.png", image_stem, atlases.len());
+        let mut image = RgbaImage {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        };
+        let mut frames = BTreeMap::new();
+        for &(idx, rect) in placed.iter() {
+            let sprite = &sprites[idx];
+            let bounds = pending
+                .iter()
+                .find(|(pending_idx, _)| *pending_idx == idx)
+                .map(|(_, bounds)| *bounds)
+                .expect("Placed sprites are pending!");
+            blit(&mut image, &sprite.image, &bounds, rect.x, rect.y);
+
+            let source_size = Size {
+                w: sprite.image.width,
+                h: sprite.image.height,
+            };
+            frames.insert(
+                sprite.name.clone(),
+                Frame {
+                    frame: rect,
+                    rotated: false,
+                    trimmed: bounds.w != source_size.w || bounds.h != source_size.h,
+                    sprite_source_size: Rect {
+                        x: bounds.x,
+                        y: bounds.y,
+                        w: bounds.w,
+                        h: bounds.h,
+                    },
+                    source_size,
+                    anchor: Point {
+                        x: sprite.offset_x as f32 / std::cmp::max(source_size.w, 1) as f32,
+                        y: sprite.offset_y as f32 / std::cmp::max(source_size.h, 1) as f32,
+                    },
+                    offset: Point {
+                        x: sprite.offset_x,
+                        y: sprite.offset_y,
+                    },
+                },
+            );
+        }
+
+        pending.retain(|(idx, _)| !placed.iter().any(|(placed_idx, _)| placed_idx == idx));
+        atlases.push(Atlas {
+            image,
+            frame_map: FrameMap {
+                frames,
+                meta: Meta {
+                    app: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                    image: image_name,
+                    format: "RGBA8888",
+                    size: Size {
+                        w: width,
+                        h: height,
+                    },
+                    scale: "1",
+                },
+            },
+        });
+    }
+
+    (atlases, oversized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opaque sprite with a color derived from the seed, so every sprite is distinguishable.
+    fn sprite(name: &str, width: u32, height: u32, seed: u8) -> Sprite {
+        let pixels = (0..width * height)
+            .flat_map(|idx| vec![seed, idx as u8, 255 - seed, 255])
+            .collect();
+        Sprite {
+            name: name.to_string(),
+            image: RgbaImage {
+                width,
+                height,
+                pixels,
+            },
+            offset_x: i32::from(seed),
+            offset_y: -i32::from(seed),
+        }
+    }
+
+    fn encode(atlases: &[Atlas]) -> Vec<(Vec<u8>, String)> {
+        atlases
+            .iter()
+            .map(|atlas| {
+                (
+                    atlas.image.encode_png().unwrap(),
+                    serde_json::to_string(&atlas.frame_map).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packing_ignores_sprite_order() {
+        let sprites = vec![
+            sprite("a", 10, 12, 1),
+            sprite("b", 7, 3, 2),
+            sprite("c", 10, 12, 3),
+            sprite("d", 30, 5, 4),
+            sprite("e", 1, 1, 5),
+        ];
+        let (atlases, oversized) = pack(&sprites, "test", 64, false);
+        assert!(oversized.is_empty());
+
+        let mut shuffled = sprites.clone();
+        shuffled.reverse();
+        shuffled.swap(0, 2);
+        let (shuffled_atlases, _) = pack(&shuffled, "test", 64, false);
+        assert_eq!(encode(&shuffled_atlases), encode(&atlases));
+    }
+
+    #[test]
+    fn atlases_have_power_of_two_sizes() {
+        let sprites: Vec<Sprite> = (0..12)
+            .map(|idx| sprite(&format!("s{}", idx), 9 + idx, 17 - idx, idx as u8))
+            .collect();
+        let (atlases, _) = pack(&sprites, "test", 1024, false);
+        assert_eq!(atlases.len(), 1);
+        for atlas in atlases.iter() {
+            let size = atlas.frame_map.meta.size;
+            assert!(size.w.is_power_of_two() && size.h.is_power_of_two());
+            assert!(size.w >= MIN_SIZE && size.h >= MIN_SIZE);
+            assert_eq!((atlas.image.width, atlas.image.height), (size.w, size.h));
+            assert_eq!(atlas.frame_map.frames.len(), 12);
+        }
+    }
+
+    #[test]
+    fn sprites_are_split_over_atlases_of_the_maximum_size() {
+        // NOTE; With padding, only one of these sprites fits on an atlas of 32 pixels.
+        let sprites = vec![
+            sprite("a", 20, 20, 1),
+            sprite("b", 20, 20, 2),
+            sprite("c", 20, 20, 3),
+            sprite("huge", 32, 8, 4),
+        ];
+        let (atlases, oversized) = pack(&sprites, "test", 32, false);
+        assert_eq!(oversized, vec![String::from("huge")]);
+
+        let names: Vec<(&str, Vec<&String>)> = atlases
+            .iter()
+            .map(|atlas| {
+                (
+                    atlas.frame_map.meta.image.as_str(),
+                    atlas.frame_map.frames.keys().collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("test_0.png", vec![&String::from("a")]),
+                ("test_1.png", vec![&String::from("b")]),
+                ("test_2.png", vec![&String::from("c")]),
+            ]
+        );
+        for atlas in atlases.iter() {
+            assert_eq!(atlas.frame_map.meta.size, Size { w: 32, h: 32 });
+        }
+    }
+
+    #[test]
+    fn trimmed_frames_keep_source_geometry() {
+        // NOTE; Only a 2x1 block at (1, 2) is visible.
+        let mut trimmed = sprite("trimmed", 4, 4, 9);
+        for (idx, pixel) in trimmed.image.pixels.chunks_mut(4).enumerate() {
+            if idx != 9 && idx != 10 {
+                pixel.copy_from_slice(&[0, 0, 0, 0]);
+            }
+        }
+        let (atlases, _) = pack(&[trimmed.clone()], "test", 64, true);
+        let frame = &atlases[0].frame_map.frames["trimmed"];
+
+        assert!(frame.trimmed);
+        assert_eq!(frame.frame.w, 2);
+        assert_eq!(frame.frame.h, 1);
+        assert_eq!(
+            frame.sprite_source_size,
+            Rect {
+                x: 1,
+                y: 2,
+                w: 2,
+                h: 1,
+            }
+        );
+        assert_eq!(frame.source_size, Size { w: 4, h: 4 });
+        assert_eq!(frame.offset, Point { x: 9, y: -9 });
+        assert_eq!(frame.anchor, Point { x: 2.25, y: -2.25 });
+
+        let image = &atlases[0].image;
+        let idx = ((frame.frame.y * image.width + frame.frame.x) * 4) as usize;
+        assert_eq!(image.pixels[idx..idx + 8], trimmed.image.pixels[36..44]);
+
+        let (untrimmed, _) = pack(&[trimmed], "test", 64, false);
+        let frame = &untrimmed[0].frame_map.frames["trimmed"];
+        assert!(!frame.trimmed);
+        assert_eq!(
+            frame.sprite_source_size,
+            Rect {
+                x: 0,
+                y: 0,
+                w: 4,
+                h: 4
+            }
+        );
+    }
+}
@@ -0,0 +1,522 @@
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+use swf::{BitmapFormat, DefineBitsLossless};
+
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const JPEG_EOI: [u8; 2] = [0xFF, 0xD9];
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const GIF_SIGNATURE: &[u8] = b"GIF89a";
+
+#[derive(Debug)]
+pub enum Error {
+    Zlib(io::Error),
+    Png(png::EncodingError),
+    PngDecoding(png::DecodingError),
+    Jpeg(jpeg_decoder::Error),
+    UnsupportedImage(&'static str),
+    AlphaMismatch { expected: usize, actual: usize },
+    Truncated { expected: usize, actual: usize },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Error::Zlib(ref io) => write!(f, "Corrupt zlib data: {}", io),
+            Error::Png(ref png) => write!(f, "PNG encoding error: {}", png),
+            Error::PngDecoding(ref png) => write!(f, "PNG decoding error: {}", png),
+            Error::Jpeg(ref jpeg) => write!(f, "JPEG decoding error: {}", jpeg),
+            Error::UnsupportedImage(kind) => write!(f, "Unsupported embedded image type {}", kind),
+            Error::AlphaMismatch { expected, actual } => write!(
+                f,
+                "Alpha channel holds {} values, the image has {} pixels",
+                actual, expected
+            ),
+            Error::Truncated { expected, actual } => write!(
+                f,
+                "Bitmap data truncated, expected {} bytes but found {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decoded image with straight (not premultiplied) alpha, 4 bytes per pixel in RGBA order.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn decode_png(data: &[u8]) -> Result<Self, Error> {
+        decode_png_stream(data)
+    }
+
+    pub fn flip_horizontal(&self) -> RgbaImage {
+        if self.width == 0 {
+            return self.clone();
+        }
+        let row_length = self.width as usize * 4;
+        let pixels = self
+            .pixels
+            .chunks(row_length)
+            .flat_map(|row| row.chunks(4).rev().flatten().cloned().collect::<Vec<u8>>())
+            .collect();
+        RgbaImage {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+
+    pub fn flip_vertical(&self) -> RgbaImage {
+        if self.width == 0 {
+            return self.clone();
+        }
+        let row_length = self.width as usize * 4;
+        let pixels = self
+            .pixels
+            .chunks(row_length)
+            .rev()
+            .flatten()
+            .cloned()
+            .collect();
+        RgbaImage {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+
+    pub fn encode_png(&self) -> Result<Vec<u8>, Error> {
+        let mut buffer = Vec::new();
+        {
+            let mut png_encoder = png::Encoder::new(&mut buffer, self.width, self.height);
+            png_encoder.set_color(png::ColorType::RGBA);
+            png_encoder.set_depth(png::BitDepth::Eight);
+            let mut png_writer = png_encoder.write_header().map_err(Error::Png)?;
+            png_writer
+                .write_image_data(&self.pixels)
+                .map_err(Error::Png)?;
+        }
+        Ok(buffer)
+    }
+}
+
+/// Rows of lossless bitmaps are padded to a multiple of 32 bits.
+fn padded_row_length(row_length: usize) -> usize {
+    (row_length + 3) & !3
+}
+
+fn ensure_length(data: &[u8], expected: usize) -> Result<(), Error> {
+    match data.len() < expected {
+        true => Err(Error::Truncated {
+            expected,
+            actual: data.len(),
+        }),
+        false => Ok(()),
+    }
+}
+
+/// Converts a premultiplied color channel back into a straight color channel.
+fn unpremultiply(channel: u8, alpha: u8) -> u8 {
+    match alpha {
+        0 => 0,
+        255 => channel,
+        alpha => {
+            let alpha = u32::from(alpha);
+            std::cmp::min(255, (u32::from(channel) * 255 + alpha / 2) / alpha) as u8
+        }
+    }
+}
+
+/// Decodes the decompressed pixel data of a DefineBitsLossless (version 1) or DefineBitsLossless2
+/// (version 2) tag into straight alpha RGBA.
+///
+/// NOTE; Version 2 stores premultiplied alpha, version 1 has no alpha and is fully opaque.
+pub(crate) fn decode_pixels(
+    format: &BitmapFormat,
+    version: u8,
+    width: usize,
+    height: usize,
+    num_colors: usize,
+    data: &[u8],
+) -> Result<Vec<u8>, Error> {
+    // NOTE; Empty bitmaps are valid, their padded row length is zero and can't be chunked.
+    if width == 0 || height == 0 {
+        return Ok(Vec::new());
+    }
+
+    let has_alpha = version >= 2;
+    let mut pixels = Vec::with_capacity(width * height * 4);
+
+    match *format {
+        BitmapFormat::ColorMap8 => {
+            // NOTE; The stored color count is one less than the actual size of the color table.
+            let color_count = num_colors + 1;
+            let color_length = if has_alpha { 4 } else { 3 };
+            let table_length = color_count * color_length;
+            let row_length = padded_row_length(width);
+            ensure_length(data, table_length + row_length * height)?;
+
+            let (table, indices) = data.split_at(table_length);
+            let palette: Vec<[u8; 4]> = table
+                .chunks(color_length)
+                .map(|color| match has_alpha {
+                    true => [
+                        unpremultiply(color[0], color[3]),
+                        unpremultiply(color[1], color[3]),
+                        unpremultiply(color[2], color[3]),
+                        color[3],
+                    ],
+                    false => [color[0], color[1], color[2], 255],
+                })
+                .collect();
+
+            for row in indices.chunks(row_length).take(height) {
+                for &index in row[..width].iter() {
+                    // NOTE; Indices outside the color table are rendered transparent.
+                    let color = palette.get(index as usize).cloned().unwrap_or([0, 0, 0, 0]);
+                    pixels.extend_from_slice(&color);
+                }
+            }
+        }
+        BitmapFormat::Rgb15 => {
+            let row_length = padded_row_length(width * 2);
+            ensure_length(data, row_length * height)?;
+
+            // NOTE; Each channel holds 5 bits, they're scaled up to 8 bits by repeating the high bits.
+            let expand = |value: u16| -> u8 {
+                let value = (value & 0x1F) as u8;
+                (value << 3) | (value >> 2)
+            };
+            for row in data.chunks(row_length).take(height) {
+                for pixel in row[..width * 2].chunks(2) {
+                    let value = u16::from_be_bytes([pixel[0], pixel[1]]);
+                    pixels.extend_from_slice(&[
+                        expand(value >> 10),
+                        expand(value >> 5),
+                        expand(value),
+                        255,
+                    ]);
+                }
+            }
+        }
+        BitmapFormat::Rgb32 => {
+            ensure_length(data, width * height * 4)?;
+
+            // NOTE; Pixels are stored as ARGB, or reserved byte and RGB without alpha.
+            for pixel in data.chunks(4).take(width * height) {
+                match has_alpha {
+                    true => pixels.extend_from_slice(&[
+                        unpremultiply(pixel[1], pixel[0]),
+                        unpremultiply(pixel[2], pixel[0]),
+                        unpremultiply(pixel[3], pixel[0]),
+                        pixel[0],
+                    ]),
+                    false => pixels.extend_from_slice(&[pixel[1], pixel[2], pixel[3], 255]),
+                }
+            }
+        }
+    }
+
+    Ok(pixels)
+}
+
+pub(crate) fn decode_lossless(bitmap: &DefineBitsLossless) -> Result<RgbaImage, Error> {
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(&bitmap.data[..])
+        .read_to_end(&mut decompressed)
+        .map_err(Error::Zlib)?;
+
+    let pixels = decode_pixels(
+        &bitmap.format,
+        bitmap.version,
+        bitmap.width as usize,
+        bitmap.height as usize,
+        bitmap.num_colors as usize,
+        &decompressed,
+    )?;
+
+    Ok(RgbaImage {
+        width: u32::from(bitmap.width),
+        height: u32::from(bitmap.height),
+        pixels,
+    })
+}
+
+/// Removes the erroneous header and stray EOI/SOI marker pairs which older Flash tools wrote.
+///
+/// NOTE; The marker pair can't occur inside entropy coded data because each 0xFF byte is stuffed there.
+fn strip_invalid_markers(data: &[u8]) -> Vec<u8> {
+    let erroneous_marker = [JPEG_EOI[0], JPEG_EOI[1], JPEG_SOI[0], JPEG_SOI[1]];
+    let mut data = data;
+    while data.starts_with(&erroneous_marker) {
+        data = &data[4..];
+    }
+
+    let mut cleaned = Vec::with_capacity(data.len());
+    let mut idx = 0;
+    while idx < data.len() {
+        // NOTE; The leading SOI marker is never preceded by an EOI, so index 0 is left alone.
+        if idx > 0 && data[idx..].starts_with(&erroneous_marker) {
+            idx += 4;
+            continue;
+        }
+        cleaned.push(data[idx]);
+        idx += 1;
+    }
+    cleaned
+}
+
+/// Prefixes the encoding tables of a JPEGTables tag onto the image data of a DefineBits tag.
+fn merge_jpeg_tables(jpeg_tables: &[u8], data: &[u8]) -> Vec<u8> {
+    let tables = strip_invalid_markers(jpeg_tables);
+    let image = strip_invalid_markers(data);
+
+    // NOTE; The tables end with EOI and the image starts with SOI, both are dropped to form one stream.
+    let tables = match tables.ends_with(&JPEG_EOI) {
+        true => &tables[..tables.len() - 2],
+        false => &tables[..],
+    };
+    let image = match image.starts_with(&JPEG_SOI) && !tables.is_empty() {
+        true => &image[2..],
+        false => &image[..],
+    };
+
+    let mut merged = Vec::with_capacity(tables.len() + image.len());
+    merged.extend_from_slice(tables);
+    merged.extend_from_slice(image);
+    merged
+}
+
+fn decode_jpeg_stream(data: &[u8]) -> Result<RgbaImage, Error> {
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let decoded = decoder.decode().map_err(Error::Jpeg)?;
+    let info = decoder
+        .info()
+        .ok_or(Error::UnsupportedImage("JPEG without frame header"))?;
+
+    let pixels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => decoded.iter().flat_map(|&l| vec![l, l, l, 255]).collect(),
+        jpeg_decoder::PixelFormat::RGB24 => decoded
+            .chunks(3)
+            .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        jpeg_decoder::PixelFormat::CMYK32 => return Err(Error::UnsupportedImage("CMYK JPEG")),
+    };
+
+    Ok(RgbaImage {
+        width: u32::from(info.width),
+        height: u32::from(info.height),
+        pixels,
+    })
+}
+
+fn decode_png_stream(data: &[u8]) -> Result<RgbaImage, Error> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info().map_err(Error::PngDecoding)?;
+    let mut decoded = vec![0; info.buffer_size()];
+    reader
+        .next_frame(&mut decoded)
+        .map_err(Error::PngDecoding)?;
+
+    let pixels = match info.color_type {
+        png::ColorType::Grayscale => decoded.iter().flat_map(|&l| vec![l, l, l, 255]).collect(),
+        png::ColorType::GrayscaleAlpha => decoded
+            .chunks(2)
+            .flat_map(|la| vec![la[0], la[0], la[0], la[1]])
+            .collect(),
+        png::ColorType::RGB => decoded
+            .chunks(3)
+            .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        png::ColorType::RGBA => decoded,
+        png::ColorType::Indexed => return Err(Error::UnsupportedImage("unexpanded indexed PNG")),
+    };
+
+    Ok(RgbaImage {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+/// Decodes the image data of a DefineBits, DefineBitsJpeg2, DefineBitsJpeg3 or DefineBitsJpeg4 tag.
+///
+/// DefineBits relies on the encoding tables of the JPEGTables tag, the other tags are self contained and
+/// might embed PNG data instead. DefineBitsJpeg3 and DefineBitsJpeg4 carry a separate zlib compressed alpha
+/// plane holding one byte per pixel.
+pub(crate) fn decode_jpeg(
+    jpeg_tables: Option<&[u8]>,
+    data: &[u8],
+    alpha_data: Option<&[u8]>,
+) -> Result<RgbaImage, Error> {
+    let mut image = if data.starts_with(&PNG_SIGNATURE) {
+        decode_png_stream(data)?
+    } else if data.starts_with(GIF_SIGNATURE) {
+        return Err(Error::UnsupportedImage("GIF"));
+    } else {
+        let stream = match jpeg_tables {
+            Some(tables) => merge_jpeg_tables(tables, data),
+            None => strip_invalid_markers(data),
+        };
+        decode_jpeg_stream(&stream)?
+    };
+
+    // NOTE; Embedded PNG images carry their own alpha, the alpha plane only applies to JPEG data.
+    match alpha_data {
+        Some(alpha_data) if !alpha_data.is_empty() && !data.starts_with(&PNG_SIGNATURE) => {
+            let mut alpha = Vec::new();
+            ZlibDecoder::new(alpha_data)
+                .read_to_end(&mut alpha)
+                .map_err(Error::Zlib)?;
+
+            let pixel_count = (image.width * image.height) as usize;
+            if alpha.len() < pixel_count {
+                return Err(Error::AlphaMismatch {
+                    expected: pixel_count,
+                    actual: alpha.len(),
+                });
+            }
+            // NOTE; Colors are premultiplied by the alpha plane, compression artifacts can exceed it.
+            for (pixel, &alpha) in image.pixels.chunks_mut(4).zip(alpha.iter()) {
+                for channel in pixel[..3].iter_mut() {
+                    *channel = unpremultiply(std::cmp::min(*channel, alpha), alpha);
+                }
+                pixel[3] = alpha;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+
+    use super::*;
+
+    #[test]
+    fn color_map_without_alpha() {
+        // NOTE; Two colors, rows of 3 indices padded to 4 bytes. Index 5 lies outside the table.
+        let data = [
+            255, 0, 0, 0, 0, 255, //
+            0, 1, 0, 0, //
+            1, 5, 0, 0,
+        ];
+        let pixels = decode_pixels(&BitmapFormat::ColorMap8, 1, 3, 2, 1, &data).unwrap();
+        assert_eq!(
+            pixels,
+            vec![
+                255, 0, 0, 255, 0, 0, 255, 255, 255, 0, 0, 255, //
+                0, 0, 255, 255, 0, 0, 0, 0, 255, 0, 0, 255,
+            ]
+        );
+    }
+
+    #[test]
+    fn color_map_with_premultiplied_alpha() {
+        let data = [
+            128, 0, 0, 128, 0, 0, 0, 0, //
+            0, 1, 0, 0,
+        ];
+        let pixels = decode_pixels(&BitmapFormat::ColorMap8, 2, 2, 1, 1, &data).unwrap();
+        assert_eq!(pixels, vec![255, 0, 0, 128, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rgb15_expands_channels() {
+        // NOTE; One pixel per row, padded to 4 bytes. 0x7C10 is red 31 and blue 16, 0x03E0 is green 31.
+        let data = [0x7C, 0x10, 0, 0, 0x03, 0xE0, 0, 0];
+        let pixels = decode_pixels(&BitmapFormat::Rgb15, 1, 1, 2, 0, &data).unwrap();
+        assert_eq!(pixels, vec![255, 0, 132, 255, 0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn rgb32_without_alpha_is_opaque() {
+        let data = [0, 10, 20, 30, 99, 40, 50, 60];
+        let pixels = decode_pixels(&BitmapFormat::Rgb32, 1, 2, 1, 0, &data).unwrap();
+        assert_eq!(pixels, vec![10, 20, 30, 255, 40, 50, 60, 255]);
+    }
+
+    #[test]
+    fn rgb32_with_premultiplied_alpha() {
+        let data = [64, 32, 0, 64, 0, 0, 0, 0, 255, 1, 2, 3];
+        let pixels = decode_pixels(&BitmapFormat::Rgb32, 2, 3, 1, 0, &data).unwrap();
+        assert_eq!(pixels, vec![128, 0, 255, 64, 0, 0, 0, 0, 1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn empty_bitmaps_decode() {
+        let formats = [
+            BitmapFormat::ColorMap8,
+            BitmapFormat::Rgb15,
+            BitmapFormat::Rgb32,
+        ];
+        let empty: Vec<u8> = Vec::new();
+        for format in formats.iter() {
+            assert_eq!(decode_pixels(format, 2, 0, 3, 0, &[]).unwrap(), empty);
+            assert_eq!(decode_pixels(format, 2, 3, 0, 0, &[]).unwrap(), empty);
+        }
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        match decode_pixels(&BitmapFormat::Rgb32, 2, 2, 2, 0, &[0; 12]) {
+            Err(Error::Truncated { expected, actual }) => assert_eq!((expected, actual), (16, 12)),
+            other => panic!("Expected truncated data, got {:?}", other),
+        }
+    }
+
+    /// Baseline JPEG of 8x8 gray pixels of value 64, using single bit Huffman codes.
+    fn gray_jpeg() -> Vec<u8> {
+        let mut data = JPEG_SOI.to_vec();
+        // NOTE; Quantization by one, the DC coefficient is stored as is.
+        data.extend_from_slice(&[0xFF, 0xDB, 0x00, 0x43, 0x00]);
+        data.extend_from_slice(&[1; 64]);
+        data.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 8, 0, 8, 0, 8, 1, 1, 0x11, 0]);
+        // NOTE; The DC table only codes category 10, the AC table only the end of block.
+        data.extend_from_slice(&[0xFF, 0xC4, 0x00, 0x14, 0x00, 1]);
+        data.extend_from_slice(&[0; 15]);
+        data.push(10);
+        data.extend_from_slice(&[0xFF, 0xC4, 0x00, 0x14, 0x10, 1]);
+        data.extend_from_slice(&[0; 15]);
+        data.push(0x00);
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x08, 1, 1, 0x00, 0, 63, 0]);
+        // NOTE; DC difference -512 which is (64 - 128) * 8, end of block, padding ones.
+        data.extend_from_slice(&[0b0011_1111, 0b1110_1111]);
+        data.extend_from_slice(&JPEG_EOI);
+        data
+    }
+
+    #[test]
+    fn jpeg_alpha_plane_unpremultiplies_colors() {
+        let mut alpha = vec![255; 64];
+        alpha[..4].copy_from_slice(&[128, 32, 0, 255]);
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&alpha).unwrap();
+        let alpha_data = encoder.finish().unwrap();
+
+        let image = decode_jpeg(None, &gray_jpeg(), Some(&alpha_data)).unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+        assert_eq!(
+            image.pixels[..16],
+            [
+                128, 128, 128, 128, // half transparent, doubled
+                255, 255, 255, 32, // clamped to the alpha before unpremultiplying
+                0, 0, 0, 0, // fully transparent
+                64, 64, 64, 255, // opaque, untouched
+            ]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::{header, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+use crate::runtime;
+
+/// Validators of a cached file, stored next to it as `<file name>.meta.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) struct CacheRecord {
+    /// Seconds since the unix epoch at which the file was last downloaded or revalidated.
+    pub fetched_at: u64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Url of the resource after following all redirects.
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FetchOutcome {
+    /// The cached file is younger than the cache time, no request was made.
+    Cached,
+    /// The server confirmed the cached file is still current.
+    NotModified,
+    /// A new copy was written, holds the amount of bytes.
+    Downloaded(u64),
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+pub(crate) fn record_path(file: &Path) -> PathBuf {
+    let mut record_name = file.file_name().map(OsString::from).unwrap_or_default();
+    record_name.push(".meta.json");
+    file.with_file_name(record_name)
+}
+
+impl CacheRecord {
+    pub fn from_response(response: &Response) -> Self {
+        let header_value = |name: header::HeaderName| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+
+        CacheRecord {
+            fetched_at: now(),
+            etag: header_value(header::ETAG),
+            last_modified: header_value(header::LAST_MODIFIED),
+            target: response.url().to_string(),
+        }
+    }
+
+    /// Loads the record of the provided file, a missing or corrupt record counts as no record.
+    pub fn load(file: &Path) -> Option<Self> {
+        let record_file = File::open(record_path(file)).ok()?;
+        serde_json::from_reader(record_file).ok()
+    }
+
+    pub fn store(&self, file: &Path) -> Result<(), runtime::Error> {
+        let record_file = File::create(record_path(file)).map_err(runtime::Error::Io)?;
+        serde_json::to_writer_pretty(record_file, self)
+            .map_err(|error| runtime::Error::Io(error.into()))
+    }
+
+    pub fn is_fresh(&self, cache_time: Duration) -> bool {
+        now().saturating_sub(self.fetched_at) < cache_time.as_secs()
+    }
+
+    pub fn touch(&mut self) {
+        self.fetched_at = now();
+    }
+
+    /// Turns the request into a conditional request against the stored validators.
+    pub fn revalidate(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(ref etag) = self.etag {
+            request = request.header(header::IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(ref last_modified) = self.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+        request
+    }
+}
+
+/// Downloads the resource into the destination file, unless the cached copy is still usable.
+///
+/// Within the cache time the cached copy is used without contacting the server, afterwards the copy is
+/// revalidated with a conditional request.
+pub(crate) fn fetch(
+    client: &Client,
+    url: &Url,
+    destination: &Path,
+    cache_time: Duration,
+    logger: &slog::Logger,
+) -> Result<FetchOutcome, runtime::Error> {
+    fetch_validated(client, url, destination, cache_time, logger, |_| Ok(()))
+}
+
+/// Same as [`fetch`], but the downloaded payload must pass validation before it replaces the cached copy.
+///
+/// NOTE; The payload is written into a temporary file next to the destination, which is atomically renamed
+/// so readers never observe a partial file.
+pub(crate) fn fetch_validated<V>(
+    client: &Client,
+    url: &Url,
+    destination: &Path,
+    cache_time: Duration,
+    logger: &slog::Logger,
+    validate: V,
+) -> Result<FetchOutcome, runtime::Error>
+where
+    V: FnOnce(&Path) -> Result<(), runtime::Error>,
+{
+    let record = match destination.is_file() {
+        true => CacheRecord::load(destination),
+        false => None,
+    };
+
+    let mut request = client.get(url.clone());
+    if let Some(ref record) = record {
+        if record.is_fresh(cache_time) {
+            slog::trace!(logger, "Cache hit"; "path" => %destination.display());
+            return Ok(FetchOutcome::Cached);
+        }
+        request = record.revalidate(request);
+    }
+
+    let mut response = request
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(runtime::Error::HttpClient)?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        if let Some(mut record) = record {
+            record.touch();
+            record.store(destination)?;
+        }
+        slog::trace!(logger, "Cache revalidated"; "path" => %destination.display());
+        return Ok(FetchOutcome::NotModified);
+    }
+
+    let new_record = CacheRecord::from_response(&response);
+    let parent = destination.parent().unwrap_or_else(|| Path::new("."));
+    let mut temporary = NamedTempFile::new_in(parent).map_err(runtime::Error::Io)?;
+    let length = response
+        .copy_to(temporary.as_file_mut())
+        .map_err(runtime::Error::HttpClient)?;
+    if length == 0 {
+        return Err(runtime::Error::EmptyBody(url.clone()));
+    }
+
+    validate(temporary.path())?;
+    temporary
+        .persist(destination)
+        .map_err(|error| runtime::Error::Io(error.error))?;
+    new_record.store(destination)?;
+
+    Ok(FetchOutcome::Downloaded(length))
+}
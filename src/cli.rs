@@ -1,30 +1,97 @@
 use clap::{arg_enum, crate_authors, crate_description, crate_name, crate_version, Clap};
 use humantime::Duration;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time;
 
+use crate::atlas;
+use crate::config::{self, Settings};
 use crate::error;
+use crate::hotel::{Hotel, HotelRegistry};
+
+/// Default values of the settings which can be configured.
+const DEFAULT_DATA_PATH: &str = "./data/";
+const DEFAULT_CACHE_TIME: time::Duration = time::Duration::from_secs(10 * 24 * 60 * 60);
+const DEFAULT_ATLAS_MAX_SIZE: u32 = 2048;
+const DEFAULT_HOST_CONNECTION_LIMIT: usize = 8;
+/// Downloads mostly wait on the network, more threads than cores keep the connections busy.
+const IO_THREADS_PER_CORE: usize = 4;
+const MAX_DEFAULT_IO_THREAD_COUNT: usize = 32;
 
 pub(crate) struct CLI {
-    pub zones: Vec<String>,
-    pub version_pin: Option<String>,
     pub data_path: PathBuf,
+    pub io_thread_count: usize,
+    pub cpu_thread_count: usize,
+    /// Maximum of simultaneous downloads from one host, independent of the thread counts.
+    pub host_connection_limit: usize,
+    pub command: Command,
+}
+
+/// Stage of the extractor to run, only `download` touches the network.
+pub(crate) enum Command {
+    Download(DownloadOptions),
+    Extract(ExtractOptions),
+    Render(RenderOptions),
+    Inspect(InspectOptions),
+    Diff(DiffOptions),
+}
+
+/// Hotels to work on, and the furniture data revision to use for all of them.
+///
+/// NOTE; Without pin the offline stages use the revision of the last download.
+pub(crate) struct SourceOptions {
+    pub hotels: Vec<Hotel>,
+    pub version_pin: Option<String>,
+}
+
+pub(crate) struct DownloadOptions {
+    pub source: SourceOptions,
     pub cache_time: time::Duration,
+    pub furnidata_format: FurnidataFormat,
+    pub metadata_only: bool,
+}
+
+pub(crate) struct ExtractOptions {
+    pub source: SourceOptions,
+    pub output_format: OutputFormat,
+    pub atlas_trim: bool,
+    pub atlas_max_size: u32,
+}
+
+pub(crate) struct RenderOptions {
+    pub source: SourceOptions,
+    pub animate: bool,
+    pub gif: bool,
+    pub directions: Vec<u32>,
+    pub states: Vec<u32>,
+}
+
+pub(crate) struct InspectOptions {
+    pub path: PathBuf,
+    pub unpack: bool,
+    pub slice: bool,
+}
+
+pub(crate) struct DiffOptions {
+    pub hotels: Vec<Hotel>,
+    pub from: String,
+    pub to: String,
+}
+
+arg_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum FurnidataFormat {
+        Xml,
+        Json,
+        Auto
+    }
 }
 
 arg_enum! {
-    #[derive(Debug)]
-    enum TopLevelDomains {
-        All,
-        Com,
-        De,
-        Fi,
-        Fr,
-        It,
-        Es,
-        Nl,
-        ComBr,
-        ComTr
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum OutputFormat {
+        Loose,
+        Atlas,
+        Nitro
     }
 }
 
@@ -36,53 +103,352 @@ arg_enum! {
     about = crate_description!()
 )]
 struct CLIParse {
-    #[clap(long, default_value = stringify!(ALL))]
-    tld: TopLevelDomains,
+    #[clap(long, parse(from_os_str))]
+    data_path: Option<PathBuf>,
+
+    /// Threads downloading, by default 4 per core with a maximum of 32
+    #[clap(long = "io-threads")]
+    io_thread_count: Option<usize>,
+
+    /// Threads extracting and rendering, by default one per core
+    #[clap(long = "cpu-threads")]
+    cpu_thread_count: Option<usize>,
+
+    /// Simultaneous downloads from one host
+    #[clap(long)]
+    host_connection_limit: Option<usize>,
+
+    /// Configuration file, by default `config.toml` inside the data path or the XDG config folder
+    #[clap(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: CommandParse,
+}
+
+#[derive(Clap)]
+enum CommandParse {
+    /// Downloads furniture data and asset packs
+    Download(DownloadParse),
+    /// Extracts downloaded asset packs
+    Extract(ExtractParse),
+    /// Renders previews and animations of extracted asset packs
+    Render(RenderParse),
+    /// Describes a SWF file or Nitro bundle
+    Inspect(InspectParse),
+    /// Lists furniture changes between two furniture data revisions
+    Diff(DiffParse),
+}
+
+#[derive(Clap)]
+struct HotelsParse {
+    /// Name of a registered hotel or a `name=..;gamedata=..;furni=..` definition, repeatable
+    #[clap(long = "hotel", number_of_values = 1)]
+    hotels: Vec<String>,
+
+    #[clap(long, parse(from_os_str))]
+    hotels_file: Option<PathBuf>,
+}
+
+#[derive(Clap)]
+struct SourceParse {
+    #[clap(flatten)]
+    hotels: HotelsParse,
 
     #[clap(long)]
     version_pin: Option<String>,
+}
+
+#[derive(Clap)]
+struct DownloadParse {
+    #[clap(flatten)]
+    source: SourceParse,
 
-    #[clap(long, parse(from_os_str), default_value = "./data/")]
-    data_path: PathBuf,
+    #[clap(long)]
+    cache_time: Option<Duration>,
+
+    #[clap(long)]
+    furnidata_format: Option<FurnidataFormat>,
 
-    #[clap(long, default_value = "10d")]
-    cache_time: Duration,
+    #[clap(long)]
+    metadata_only: bool,
 }
 
-fn parse_tld(tld: TopLevelDomains) -> Vec<String> {
-    fn convert_tld(item: TopLevelDomains) -> String {
-        match item {
-            TopLevelDomains::All => panic!("Method shouldn't be called with variant All"),
-            TopLevelDomains::ComBr => String::from("com.br"),
-            TopLevelDomains::ComTr => String::from("com.tr"),
-            other => format!("{}", other),
-        }
-    }
+#[derive(Clap)]
+struct ExtractParse {
+    #[clap(flatten)]
+    source: SourceParse,
+
+    #[clap(long)]
+    format: Option<OutputFormat>,
 
-    match tld {
-        TopLevelDomains::All => vec![
-            convert_tld(TopLevelDomains::Com),
-            convert_tld(TopLevelDomains::De),
-            convert_tld(TopLevelDomains::Fi),
-            convert_tld(TopLevelDomains::Fr),
-            convert_tld(TopLevelDomains::It),
-            convert_tld(TopLevelDomains::Es),
-            convert_tld(TopLevelDomains::Nl),
-            convert_tld(TopLevelDomains::ComBr),
-            convert_tld(TopLevelDomains::ComTr),
-        ],
-        other => vec![convert_tld(other)],
+    #[clap(long, overrides_with = "no-atlas-trim")]
+    atlas_trim: bool,
+
+    /// Turns atlas trimming off, even when the configuration file or environment turns it on
+    #[clap(long, overrides_with = "atlas-trim")]
+    no_atlas_trim: bool,
+
+    #[clap(long)]
+    atlas_max_size: Option<u32>,
+}
+
+#[derive(Clap)]
+struct RenderParse {
+    #[clap(flatten)]
+    source: SourceParse,
+
+    #[clap(long)]
+    animate: bool,
+
+    #[clap(long, requires = "animate")]
+    gif: bool,
+
+    #[clap(long, use_delimiter = true, requires = "animate")]
+    directions: Vec<u32>,
+
+    #[clap(long, use_delimiter = true, requires = "animate")]
+    states: Vec<u32>,
+}
+
+#[derive(Clap)]
+struct InspectParse {
+    #[clap(parse(from_os_str))]
+    path: PathBuf,
+
+    #[clap(long)]
+    unpack: bool,
+
+    #[clap(long, requires = "unpack")]
+    slice: bool,
+}
+
+#[derive(Clap)]
+struct DiffParse {
+    #[clap(flatten)]
+    hotels: HotelsParse,
+
+    from: String,
+
+    to: String,
+}
+
+/// Settings given on the command line, the highest layer of configuration.
+fn command_settings(parsed: &CLIParse) -> Settings {
+    let hotels = |parsed: &HotelsParse| match parsed.hotels.is_empty() {
+        true => None,
+        false => Some(parsed.hotels.clone()),
+    };
+    let settings = Settings {
+        data_path: parsed.data_path.clone(),
+        io_thread_count: parsed.io_thread_count,
+        cpu_thread_count: parsed.cpu_thread_count,
+        host_connection_limit: parsed.host_connection_limit,
+        ..Settings::default()
+    };
+    match parsed.command {
+        CommandParse::Download(ref download) => Settings {
+            cache_time: download.cache_time.map(Into::into),
+            furnidata_format: download.furnidata_format,
+            hotels: hotels(&download.source.hotels),
+            ..settings
+        },
+        CommandParse::Extract(ref extract) => Settings {
+            output_format: extract.format,
+            atlas_trim: match (extract.atlas_trim, extract.no_atlas_trim) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            atlas_max_size: extract.atlas_max_size,
+            hotels: hotels(&extract.source.hotels),
+            ..settings
+        },
+        CommandParse::Render(ref render) => Settings {
+            hotels: hotels(&render.source.hotels),
+            ..settings
+        },
+        CommandParse::Diff(ref diff) => Settings {
+            hotels: hotels(&diff.hotels),
+            ..settings
+        },
+        CommandParse::Inspect(_) => settings,
     }
 }
 
-pub(crate) fn get_cli() -> Result<CLI, error::ExtractorError> {
-    match CLIParse::try_parse() {
-        Ok(parsed) => Ok(CLI {
-            zones: parse_tld(parsed.tld),
-            version_pin: parsed.version_pin,
-            data_path: parsed.data_path,
-            cache_time: parsed.cache_time.into(),
+fn parse_hotels(
+    parsed: HotelsParse,
+    settings: &Settings,
+    data_path: &Path,
+) -> Result<Vec<Hotel>, error::ExtractorError> {
+    let mut registry = HotelRegistry::discover(parsed.hotels_file.as_deref(), data_path)
+        .map_err(error::ExtractorError::Other)?;
+    registry
+        .extend(settings.hotel_entries.clone())
+        .map_err(error::ExtractorError::Other)?;
+    registry
+        .select(settings.hotels.as_deref().unwrap_or_default())
+        .map_err(error::ExtractorError::Other)
+}
+
+fn parse_source(
+    parsed: SourceParse,
+    settings: &Settings,
+    data_path: &Path,
+) -> Result<SourceOptions, error::ExtractorError> {
+    Ok(SourceOptions {
+        hotels: parse_hotels(parsed.hotels, settings, data_path)?,
+        version_pin: parsed.version_pin,
+    })
+}
+
+fn parse_command(
+    parsed: CommandParse,
+    settings: &Settings,
+    data_path: &Path,
+) -> Result<Command, error::ExtractorError> {
+    let command = match parsed {
+        CommandParse::Download(parsed) => Command::Download(DownloadOptions {
+            source: parse_source(parsed.source, settings, data_path)?,
+            cache_time: settings.cache_time.unwrap_or(DEFAULT_CACHE_TIME),
+            furnidata_format: settings.furnidata_format.unwrap_or(FurnidataFormat::Auto),
+            metadata_only: parsed.metadata_only,
+        }),
+        CommandParse::Extract(parsed) => {
+            let atlas_max_size = settings.atlas_max_size.unwrap_or(DEFAULT_ATLAS_MAX_SIZE);
+            if !atlas_max_size.is_power_of_two() {
+                return Err(error::ExtractorError::Other(format!(
+                    "Atlas size {} is not a power of two",
+                    atlas_max_size
+                )));
+            }
+            if atlas_max_size > atlas::MAX_SIZE {
+                return Err(error::ExtractorError::Other(format!(
+                    "Atlas size {} exceeds the maximum of {}",
+                    atlas_max_size,
+                    atlas::MAX_SIZE
+                )));
+            }
+            Command::Extract(ExtractOptions {
+                source: parse_source(parsed.source, settings, data_path)?,
+                output_format: settings.output_format.unwrap_or(OutputFormat::Loose),
+                atlas_trim: settings.atlas_trim.unwrap_or(false),
+                atlas_max_size,
+            })
+        }
+        CommandParse::Render(parsed) => Command::Render(RenderOptions {
+            source: parse_source(parsed.source, settings, data_path)?,
+            animate: parsed.animate,
+            gif: parsed.gif,
+            directions: parsed.directions,
+            states: parsed.states,
         }),
-        Err(error) => Err(error::ExtractorError::Argument(error)),
+        CommandParse::Inspect(parsed) => Command::Inspect(InspectOptions {
+            path: parsed.path,
+            unpack: parsed.unpack,
+            slice: parsed.slice,
+        }),
+        CommandParse::Diff(parsed) => Command::Diff(DiffOptions {
+            hotels: parse_hotels(parsed.hotels, settings, data_path)?,
+            from: parsed.from,
+            to: parsed.to,
+        }),
+    };
+    Ok(command)
+}
+
+/// Parses the command line and merges it with the environment and configuration file.
+///
+/// NOTE; The configuration file is searched inside the data path given on the command line or through the
+/// environment, a data path inside the configuration file only applies when neither is set.
+pub(crate) fn get_cli() -> Result<CLI, error::ExtractorError> {
+    let parsed = CLIParse::try_parse().map_err(error::ExtractorError::Argument)?;
+    let command_line = command_settings(&parsed);
+    let environment = Settings::from_env().map_err(error::ExtractorError::Other)?;
+
+    let search_path = command_line
+        .data_path
+        .clone()
+        .or_else(|| environment.data_path.clone())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_PATH));
+    let file = match config::discover(parsed.config.as_deref(), &search_path)
+        .map_err(error::ExtractorError::Other)?
+    {
+        Some(path) => Settings::from_file(&path).map_err(error::ExtractorError::Other)?,
+        None => Settings::default(),
+    };
+    let settings = command_line.or(environment).or(file);
+
+    let data_path = settings
+        .data_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_PATH));
+    // NOTE; Parallelism is unknown on some platforms, a single core is assumed then.
+    let core_count = std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1);
+    let io_thread_count = settings
+        .io_thread_count
+        .unwrap_or_else(|| (core_count * IO_THREADS_PER_CORE).min(MAX_DEFAULT_IO_THREAD_COUNT));
+    let cpu_thread_count = settings.cpu_thread_count.unwrap_or(core_count);
+    let host_connection_limit = settings
+        .host_connection_limit
+        .unwrap_or(DEFAULT_HOST_CONNECTION_LIMIT);
+    if io_thread_count == 0 || cpu_thread_count == 0 || host_connection_limit == 0 {
+        return Err(error::ExtractorError::Other(String::from(
+            "Thread counts and host connection limit must be at least 1",
+        )));
+    }
+
+    Ok(CLI {
+        command: parse_command(parsed.command, &settings, &data_path)?,
+        data_path,
+        io_thread_count,
+        cpu_thread_count,
+        host_connection_limit,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(arguments: &[&str]) -> Settings {
+        command_settings(&CLIParse::try_parse_from(arguments).unwrap())
+    }
+
+    fn atlas_trim(arguments: &[&str]) -> Option<bool> {
+        settings(arguments).atlas_trim
+    }
+
+    #[test]
+    fn last_atlas_trim_flag_wins() {
+        assert_eq!(atlas_trim(&["extractor", "extract"]), None);
+        assert_eq!(
+            atlas_trim(&["extractor", "extract", "--atlas-trim"]),
+            Some(true)
+        );
+        assert_eq!(
+            atlas_trim(&["extractor", "extract", "--no-atlas-trim"]),
+            Some(false)
+        );
+        assert_eq!(
+            atlas_trim(&["extractor", "extract", "--atlas-trim", "--no-atlas-trim"]),
+            Some(false)
+        );
+        assert_eq!(
+            atlas_trim(&["extractor", "extract", "--no-atlas-trim", "--atlas-trim"]),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn no_atlas_trim_overrides_lower_layers() {
+        let lower = Settings {
+            atlas_trim: Some(true),
+            ..Settings::default()
+        };
+        let settings = settings(&["extractor", "extract", "--no-atlas-trim"]).or(lower);
+        assert_eq!(settings.atlas_trim, Some(false));
     }
 }
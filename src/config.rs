@@ -0,0 +1,359 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time;
+
+use serde::Deserialize;
+
+use crate::cli::{FurnidataFormat, OutputFormat};
+use crate::hotel::HotelEntry;
+
+/// File name of the configuration, inside the data path or the XDG configuration folder.
+pub(crate) const CONFIG_FILE: &str = "config.toml";
+/// Folder of the configuration inside the XDG configuration home.
+const CONFIG_FOLDER: &str = "bobba-asset-extractor";
+/// Prefix of the environment variables, eg `BOBBA_CACHE_TIME`.
+const ENV_PREFIX: &str = "BOBBA_";
+
+/// One layer of configuration, unset values fall through to the next layer.
+///
+/// Layers from high to low precedence: command line flags, environment variables, configuration file.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Settings {
+    pub data_path: Option<PathBuf>,
+    pub cache_time: Option<time::Duration>,
+    pub furnidata_format: Option<FurnidataFormat>,
+    pub output_format: Option<OutputFormat>,
+    pub atlas_trim: Option<bool>,
+    pub atlas_max_size: Option<u32>,
+    pub io_thread_count: Option<usize>,
+    pub cpu_thread_count: Option<usize>,
+    pub host_connection_limit: Option<usize>,
+    /// Names or definitions of the selected hotels.
+    pub hotels: Option<Vec<String>>,
+    /// Hotels added to the registry.
+    pub hotel_entries: Vec<HotelEntry>,
+}
+
+/// Configuration file layout, values are kept textual until validated.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SettingsFile {
+    data_path: Option<PathBuf>,
+    cache_time: Option<String>,
+    furnidata_format: Option<String>,
+    format: Option<String>,
+    atlas_trim: Option<bool>,
+    atlas_max_size: Option<u32>,
+    io_thread_count: Option<usize>,
+    cpu_thread_count: Option<usize>,
+    host_connection_limit: Option<usize>,
+    hotels: Option<Vec<String>>,
+    #[serde(rename = "hotel")]
+    hotel_entries: Vec<HotelEntry>,
+}
+
+fn parse_value<T, E>(source: &str, key: &str, value: Option<&str>) -> Result<Option<T>, String>
+where
+    T: FromStr<Err = E>,
+    E: std::fmt::Display,
+{
+    value
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|error| format!("{}: invalid {} `{}`: {}", source, key, value, error))
+        })
+        .transpose()
+}
+
+fn parse_duration(
+    source: &str,
+    key: &str,
+    value: Option<&str>,
+) -> Result<Option<time::Duration>, String> {
+    parse_value::<humantime::Duration, _>(source, key, value).map(|value| value.map(Into::into))
+}
+
+/// Boolean environment value, `1` and `0` are accepted next to `true` and `false`.
+struct Flag(bool);
+
+impl FromStr for Flag {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "1" | "true" => Ok(Flag(true)),
+            "0" | "false" => Ok(Flag(false)),
+            _ => Err(String::from("expected 1, 0, true or false")),
+        }
+    }
+}
+
+fn env_variable(key: &str) -> Option<String> {
+    env::var(format!("{}{}", ENV_PREFIX, key)).ok()
+}
+
+fn parse_env<T, E>(key: &str) -> Result<Option<T>, String>
+where
+    T: FromStr<Err = E>,
+    E: std::fmt::Display,
+{
+    let name = format!("{}{}", ENV_PREFIX, key);
+    parse_value("Environment", &name, env_variable(key).as_deref())
+}
+
+impl Settings {
+    /// Fills the unset values of this layer with those of the lower layer.
+    ///
+    /// NOTE; Hotel entries are combined, entries of this layer come last so they replace equally named ones.
+    pub fn or(self, lower: Settings) -> Settings {
+        let mut hotel_entries = lower.hotel_entries;
+        hotel_entries.extend(self.hotel_entries);
+        Settings {
+            data_path: self.data_path.or(lower.data_path),
+            cache_time: self.cache_time.or(lower.cache_time),
+            furnidata_format: self.furnidata_format.or(lower.furnidata_format),
+            output_format: self.output_format.or(lower.output_format),
+            atlas_trim: self.atlas_trim.or(lower.atlas_trim),
+            atlas_max_size: self.atlas_max_size.or(lower.atlas_max_size),
+            io_thread_count: self.io_thread_count.or(lower.io_thread_count),
+            cpu_thread_count: self.cpu_thread_count.or(lower.cpu_thread_count),
+            host_connection_limit: self.host_connection_limit.or(lower.host_connection_limit),
+            hotels: self.hotels.or(lower.hotels),
+            hotel_entries,
+        }
+    }
+
+    /// Reads the `BOBBA_*` environment variables, hotels are a comma separated list of names and
+    /// `BOBBA_ATLAS_TRIM` is one of `1`, `0`, `true` or `false`.
+    pub fn from_env() -> Result<Settings, String> {
+        Ok(Settings {
+            data_path: env_variable("DATA_PATH").map(PathBuf::from),
+            cache_time: parse_env::<humantime::Duration, _>("CACHE_TIME")?.map(Into::into),
+            furnidata_format: parse_env("FURNIDATA_FORMAT")?,
+            output_format: parse_env("FORMAT")?,
+            atlas_trim: parse_env::<Flag, _>("ATLAS_TRIM")?.map(|Flag(trim)| trim),
+            atlas_max_size: parse_env("ATLAS_MAX_SIZE")?,
+            io_thread_count: parse_env("IO_THREAD_COUNT")?,
+            cpu_thread_count: parse_env("CPU_THREAD_COUNT")?,
+            host_connection_limit: parse_env("HOST_CONNECTION_LIMIT")?,
+            hotels: env_variable("HOTELS").map(|hotels| {
+                hotels
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|hotel| !hotel.is_empty())
+                    .map(String::from)
+                    .collect()
+            }),
+            hotel_entries: Vec::new(),
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Settings, String> {
+        let source = path.display().to_string();
+        let content = fs::read_to_string(path)
+            .map_err(|error| format!("{}: unreadable: {}", source, error))?;
+        let file: SettingsFile =
+            toml::from_str(&content).map_err(|error| format!("{}: {}", source, error))?;
+
+        Ok(Settings {
+            data_path: file.data_path,
+            cache_time: parse_duration(&source, "cache_time", file.cache_time.as_deref())?,
+            furnidata_format: parse_value(
+                &source,
+                "furnidata_format",
+                file.furnidata_format.as_deref(),
+            )?,
+            output_format: parse_value(&source, "format", file.format.as_deref())?,
+            atlas_trim: file.atlas_trim,
+            atlas_max_size: file.atlas_max_size,
+            io_thread_count: file.io_thread_count,
+            cpu_thread_count: file.cpu_thread_count,
+            host_connection_limit: file.host_connection_limit,
+            hotels: file.hotels,
+            hotel_entries: file.hotel_entries,
+        })
+    }
+}
+
+/// Finds the configuration file, an explicit path must exist.
+///
+/// NOTE; The data path is searched before `$XDG_CONFIG_HOME/bobba-asset-extractor/`, which defaults to
+/// `$HOME/.config`.
+pub(crate) fn discover(
+    explicit: Option<&Path>,
+    data_path: &Path,
+) -> Result<Option<PathBuf>, String> {
+    if let Some(path) = explicit {
+        return match path.is_file() {
+            true => Ok(Some(path.to_path_buf())),
+            false => Err(format!(
+                "Configuration file {} doesn't exist",
+                path.display()
+            )),
+        };
+    }
+
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    let mut candidates = std::iter::once(data_path.join(CONFIG_FILE))
+        .chain(config_home.map(|home| home.join(CONFIG_FOLDER).join(CONFIG_FILE)));
+    Ok(candidates.find(|path| path.is_file()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, gamedata_url: &str) -> HotelEntry {
+        HotelEntry {
+            name: name.to_string(),
+            gamedata_url: gamedata_url.to_string(),
+            ..HotelEntry::default()
+        }
+    }
+
+    fn write_config(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn higher_layers_win() {
+        let command_line = Settings {
+            atlas_trim: Some(false),
+            hotels: Some(vec![String::from("com")]),
+            hotel_entries: vec![entry("private", "https://command-line.example.com")],
+            ..Settings::default()
+        };
+        let environment = Settings {
+            cache_time: Some(time::Duration::from_secs(1)),
+            atlas_trim: Some(true),
+            atlas_max_size: Some(1024),
+            hotel_entries: vec![entry("private", "https://environment.example.com")],
+            ..Settings::default()
+        };
+        let file = Settings {
+            data_path: Some(PathBuf::from("file")),
+            cache_time: Some(time::Duration::from_secs(2)),
+            atlas_max_size: Some(512),
+            io_thread_count: Some(3),
+            hotels: Some(vec![String::from("nl")]),
+            hotel_entries: vec![entry("other", "https://file.example.com")],
+            ..Settings::default()
+        };
+
+        let settings = command_line.or(environment).or(file);
+        assert_eq!(settings.atlas_trim, Some(false));
+        assert_eq!(settings.hotels, Some(vec![String::from("com")]));
+        assert_eq!(settings.cache_time, Some(time::Duration::from_secs(1)));
+        assert_eq!(settings.atlas_max_size, Some(1024));
+        assert_eq!(settings.io_thread_count, Some(3));
+        assert_eq!(settings.data_path, Some(PathBuf::from("file")));
+        assert_eq!(settings.cpu_thread_count, None);
+        let gamedata_urls: Vec<&str> = settings
+            .hotel_entries
+            .iter()
+            .map(|entry| entry.gamedata_url.as_str())
+            .collect();
+        assert_eq!(
+            gamedata_urls,
+            [
+                "https://file.example.com",
+                "https://environment.example.com",
+                "https://command-line.example.com"
+            ]
+        );
+    }
+
+    #[test]
+    fn file_values_are_validated() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join(CONFIG_FILE);
+
+        write_config(
+            &path,
+            "cache_time = \"2h\"\natlas_trim = true\nhotels = [\"com\"]\n\n\
+             [[hotel]]\nname = \"private\"\ngamedata_url = \"https://hotel.example.com\"\n",
+        );
+        let settings = Settings::from_file(&path).unwrap();
+        assert_eq!(settings.cache_time, Some(time::Duration::from_secs(7200)));
+        assert_eq!(settings.atlas_trim, Some(true));
+        assert_eq!(settings.hotels, Some(vec![String::from("com")]));
+        assert_eq!(settings.hotel_entries.len(), 1);
+
+        write_config(&path, "atlas_trimm = true\n");
+        assert!(Settings::from_file(&path).is_err());
+        write_config(&path, "cache_time = \"soon\"\n");
+        assert!(Settings::from_file(&path).is_err());
+    }
+
+    // NOTE; Environment variables are shared by all tests, so every check changing them is kept in
+    // this single test.
+    #[test]
+    fn environment_and_discovery() {
+        let names = [
+            "BOBBA_ATLAS_TRIM",
+            "BOBBA_CACHE_TIME",
+            "BOBBA_HOTELS",
+            "XDG_CONFIG_HOME",
+            "HOME",
+        ];
+        let previous: Vec<_> = names.iter().map(env::var_os).collect();
+
+        env::set_var("BOBBA_CACHE_TIME", "2h");
+        env::set_var("BOBBA_HOTELS", "com, nl,");
+        for (value, expected) in
+            [("1", true), ("0", false), ("true", true), ("FALSE", false)].iter()
+        {
+            env::set_var("BOBBA_ATLAS_TRIM", value);
+            let settings = Settings::from_env().unwrap();
+            assert_eq!(settings.atlas_trim, Some(*expected), "{}", value);
+            assert_eq!(settings.cache_time, Some(time::Duration::from_secs(7200)));
+            assert_eq!(
+                settings.hotels,
+                Some(vec![String::from("com"), String::from("nl")])
+            );
+        }
+        env::set_var("BOBBA_ATLAS_TRIM", "yes");
+        assert!(Settings::from_env().is_err());
+
+        let folder = tempfile::tempdir().unwrap();
+        let data_path = folder.path().join("data");
+        let config_home = folder.path().join("xdg");
+        let home = folder.path().join("home");
+        let xdg_config = config_home.join(CONFIG_FOLDER).join(CONFIG_FILE);
+        let home_config = home.join(".config").join(CONFIG_FOLDER).join(CONFIG_FILE);
+        let data_config = data_path.join(CONFIG_FILE);
+        fs::create_dir_all(&data_path).unwrap();
+        env::set_var("XDG_CONFIG_HOME", &config_home);
+        env::set_var("HOME", &home);
+
+        assert_eq!(discover(None, &data_path), Ok(None));
+        write_config(&home_config, "");
+        assert_eq!(discover(None, &data_path), Ok(None));
+        write_config(&xdg_config, "");
+        assert_eq!(discover(None, &data_path), Ok(Some(xdg_config.clone())));
+        env::set_var("XDG_CONFIG_HOME", "relative");
+        assert_eq!(discover(None, &data_path), Ok(Some(home_config.clone())));
+        write_config(&data_config, "");
+        assert_eq!(discover(None, &data_path), Ok(Some(data_config)));
+        assert_eq!(
+            discover(Some(&xdg_config), &data_path),
+            Ok(Some(xdg_config))
+        );
+        assert!(discover(Some(&folder.path().join("missing.toml")), &data_path).is_err());
+
+        for (name, value) in names.iter().zip(previous) {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use std::fmt;
 use std::error::Error;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub enum ExtractorError {
     Argument(clap::Error),
     Runtime(crate::runtime::Error),
+    UnavailableRevision(String, PathBuf),
     Other(String),
 }
 
@@ -13,6 +15,12 @@ impl fmt::Display for ExtractorError {
         match *self {
             ExtractorError::Argument(ref clap) => write!(f, "Error during argument parsing: {}", clap),
             ExtractorError::Runtime(ref runtime) => write!(f, "Runtime error: {}", runtime),
+            ExtractorError::UnavailableRevision(ref revision, ref path) => write!(
+                f,
+                "Revision {} is not available, no furniture data found in {}",
+                revision,
+                path.display()
+            ),
             ExtractorError::Other(ref string) => write!(f, "Constraint error: {}", string),
         }
     }
@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use bobba_asset_extractor::assets::{self, AssetManifest, ExtractedAsset};
+use bobba_asset_extractor::index::{self, IndexData};
+use bobba_asset_extractor::logic::{self, ObjectData};
+use bobba_asset_extractor::visualization::{self, ColorLayer, Visualization};
+use serde::Serialize;
+
+use crate::asset_extraction::AssetPack;
+use crate::furnidata::FurnitureType;
+use crate::runtime;
+
+/// Version of the [`FurniDocument`] layout.
+///
+/// NOTE; Bump on every change which removes or changes the meaning of a field, adding fields is allowed.
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+/// File name of the document, inside `<data path>/<class name>/`.
+pub(crate) const DOCUMENT_FILE: &str = "furni.json";
+
+/// Everything known about one furniture, written as `furni.json` after extraction.
+///
+/// - `schemaVersion`: layout version, see [`SCHEMA_VERSION`].
+/// - `className`: class name without color variant suffix, which is also the asset pack name.
+/// - `revision`: revision of the asset pack on the asset server.
+/// - `furniture`: every furniture data row using the asset pack, one per color variant (`classname*N`).
+///   Rows are tagged by `kind`, either `room` or `wall`, and use camelCase furniture data field names.
+/// - `index`: visualization and logic implementation names, from the index XML.
+/// - `logic`: dimensions, directions, actions, sound and particle systems, from the logic XML.
+/// - `visualizations`: layers, directions, colors and animations per size, from the visualization XML.
+/// - `extractPath`: extract folder of the asset pack, relative to the hotel folder inside the data path.
+/// - `images`: every extracted image with its registration offset, relative to `extractPath`.
+/// - `variants`: every color variant, linking the furniture data part colors to the visualization colors.
+///
+/// Documents which couldn't be read are left out, their fields are `null` or empty.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FurniDocument<'a> {
+    pub schema_version: u32,
+    pub class_name: &'a str,
+    pub revision: u32,
+    pub furniture: Vec<&'a FurnitureType>,
+    pub index: Option<IndexData>,
+    pub logic: Option<ObjectData>,
+    pub visualizations: Vec<Visualization>,
+    pub extract_path: String,
+    pub images: Vec<ExtractedAsset>,
+    pub variants: Vec<ColorVariant<'a>>,
+}
+
+/// Color variant `classname*N`, furniture data and visualizations both refer to it by index `N`.
+///
+/// - `partColors`: colors of the furniture data row, used for the catalog and inventory.
+/// - `layers`: tinted layers per visualization size, from the visualization color `N`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ColorVariant<'a> {
+    pub class_name: &'a str,
+    pub color_index: u32,
+    pub part_colors: &'a [String],
+    pub layers: BTreeMap<u32, Vec<ColorLayer>>,
+}
+
+/// Links every colored furniture data row to the color with the same index in each visualization.
+pub(crate) fn color_variants<'a>(
+    furniture: &[&'a FurnitureType],
+    visualizations: &[Visualization],
+    logger: &slog::Logger,
+) -> Vec<ColorVariant<'a>> {
+    let mut variants = Vec::new();
+    for &item in furniture.iter() {
+        let color_index = match item.color_index() {
+            Some(color_index) => color_index,
+            None => continue,
+        };
+        let layers: BTreeMap<u32, Vec<ColorLayer>> = visualizations
+            .iter()
+            .filter_map(|visualization| {
+                visualization
+                    .color(color_index)
+                    .map(|color| (visualization.size, color.layers.clone()))
+            })
+            .collect();
+        if layers.is_empty() && !visualizations.is_empty() {
+            slog::warn!(logger, "Color variant without visualization colors";
+                "class" => item.class_name(), "color" => color_index);
+        }
+        variants.push(ColorVariant {
+            class_name: item.class_name(),
+            color_index,
+            part_colors: item.part_colors(),
+            layers,
+        });
+    }
+    variants
+}
+
+/// Parses an extracted document, a missing file is no error but unreadable content is logged.
+pub(crate) fn load_document<T, E, F>(path: &Path, parse: F, logger: &slog::Logger) -> Option<T>
+where
+    E: std::fmt::Display,
+    F: FnOnce(Box<dyn BufRead>) -> Result<T, E>,
+{
+    let file = File::open(path).ok()?;
+    match parse(Box::new(BufReader::new(file))) {
+        Ok(document) => Some(document),
+        Err(error) => {
+            slog::warn!(logger, "Document unreadable"; "path" => %path.display(), "error" => %error);
+            None
+        }
+    }
+}
+
+/// Extract folder relative to the hotel folder, with `/` separators on every platform.
+///
+/// NOTE; The document is published, it must not reveal the layout of the host filesystem.
+fn relative_extract_path(pack: &AssetPack) -> String {
+    let extract_path = pack.extract_path();
+    let relative = extract_path
+        .strip_prefix(pack.runtime.get_base_data_path())
+        .unwrap_or_else(|_| Path::new(&pack.asset_name));
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub(crate) fn write_document(
+    pack: &AssetPack,
+    logger: &slog::Logger,
+) -> Result<(), runtime::Error> {
+    let extract_path = pack.extract_path();
+    let document_path = |kind: &str| extract_path.join(format!("{}_{}.xml", pack.asset_name, kind));
+
+    let mut furniture: Vec<&FurnitureType> = pack.furniture.iter().collect();
+    furniture.sort_by_key(|item| (item.color_index().unwrap_or(0), item.id()));
+
+    let manifest: Option<AssetManifest> = File::open(extract_path.join(assets::MANIFEST_FILE))
+        .ok()
+        .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok());
+
+    let visualizations = load_document(
+        &document_path("visualization"),
+        visualization::from_reader,
+        logger,
+    )
+    .map(|data| data.graphics.visualizations)
+    .unwrap_or_default();
+    let variants = color_variants(&furniture, &visualizations, logger);
+
+    let document = FurniDocument {
+        schema_version: SCHEMA_VERSION,
+        class_name: &pack.asset_name,
+        revision: pack.revision,
+        furniture,
+        index: load_document(&document_path("index"), index::from_reader, logger),
+        logic: load_document(&document_path("logic"), logic::from_reader, logger),
+        visualizations,
+        extract_path: relative_extract_path(pack),
+        images: manifest.map(|manifest| manifest.assets).unwrap_or_default(),
+        variants,
+    };
+
+    let destination_folder = pack.runtime.get_data_path().join(&pack.asset_name);
+    std::fs::create_dir_all(&destination_folder).map_err(runtime::Error::Io)?;
+    let destination =
+        File::create(destination_folder.join(DOCUMENT_FILE)).map_err(runtime::Error::Io)?;
+    serde_json::to_writer_pretty(destination, &document)
+        .map_err(|error| runtime::Error::Io(error.into()))
+}
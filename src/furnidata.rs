@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read};
+
+use serde::{Deserialize, Serialize};
+
+use crate::runtime;
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename = "furnidata")]
+pub(crate) struct FurnitureData {
+    #[serde(rename = "roomitemtypes")]
+    pub room_item_types: RoomItemTypes,
+    #[serde(rename = "wallitemtypes")]
+    pub wall_item_types: WallItemTypes,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct RoomItemTypes {
+    #[serde(rename = "furnitype", default)]
+    pub items: Vec<RoomItemType>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct WallItemTypes {
+    #[serde(rename = "furnitype", default)]
+    pub items: Vec<WallItemType>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) struct PartColors {
+    #[serde(rename(deserialize = "color"), default)]
+    pub colors: Vec<String>,
+}
+
+/// Furniture which is placed on the floor tiles of a room.
+///
+/// NOTE; Fields are read with the furniture data names, but written in camelCase like the other documents.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RoomItemType {
+    pub id: u32,
+    #[serde(rename(deserialize = "classname"))]
+    pub class_name: String,
+    pub revision: u32,
+    #[serde(rename(deserialize = "defaultdir"), default)]
+    pub default_direction: u8,
+    #[serde(rename(deserialize = "xdim"))]
+    pub x_dimension: u32,
+    #[serde(rename(deserialize = "ydim"))]
+    pub y_dimension: u32,
+    #[serde(rename(deserialize = "partcolors"), default)]
+    pub part_colors: PartColors,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename(deserialize = "adurl"), default)]
+    pub ad_url: String,
+    #[serde(rename(deserialize = "offerid"), default)]
+    pub offer_id: i32,
+    #[serde(default)]
+    pub buyout: bool,
+    #[serde(rename(deserialize = "rentofferid"), default)]
+    pub rent_offer_id: i32,
+    #[serde(rename(deserialize = "rentbuyout"), default)]
+    pub rent_buyout: bool,
+    #[serde(rename(deserialize = "bc"), default)]
+    pub builders_club: bool,
+    #[serde(rename(deserialize = "excludeddynamic"), default)]
+    pub excluded_dynamic: bool,
+    #[serde(rename(deserialize = "customparams"), default)]
+    pub custom_params: String,
+    #[serde(rename(deserialize = "specialtype"), default)]
+    pub special_type: u32,
+    #[serde(rename(deserialize = "canstandon"), default)]
+    pub can_stand_on: bool,
+    #[serde(rename(deserialize = "cansiton"), default)]
+    pub can_sit_on: bool,
+    #[serde(rename(deserialize = "canlayon"), default)]
+    pub can_lay_on: bool,
+    #[serde(rename(deserialize = "furniline"), default)]
+    pub furni_line: String,
+    #[serde(default)]
+    pub environment: String,
+    #[serde(default)]
+    pub rare: bool,
+}
+
+/// Furniture which is hung onto the walls of a room.
+///
+/// NOTE; Wall items carry no dimensions, default direction or part colors.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WallItemType {
+    pub id: u32,
+    #[serde(rename(deserialize = "classname"))]
+    pub class_name: String,
+    pub revision: u32,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename(deserialize = "adurl"), default)]
+    pub ad_url: String,
+    #[serde(rename(deserialize = "offerid"), default)]
+    pub offer_id: i32,
+    #[serde(default)]
+    pub buyout: bool,
+    #[serde(rename(deserialize = "rentofferid"), default)]
+    pub rent_offer_id: i32,
+    #[serde(rename(deserialize = "rentbuyout"), default)]
+    pub rent_buyout: bool,
+    #[serde(rename(deserialize = "bc"), default)]
+    pub builders_club: bool,
+    #[serde(rename(deserialize = "excludeddynamic"), default)]
+    pub excluded_dynamic: bool,
+    #[serde(rename(deserialize = "customparams"), default)]
+    pub custom_params: String,
+    #[serde(rename(deserialize = "specialtype"), default)]
+    pub special_type: u32,
+    #[serde(rename(deserialize = "furniline"), default)]
+    pub furni_line: String,
+    #[serde(default)]
+    pub environment: String,
+    #[serde(default)]
+    pub rare: bool,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum FurnitureType {
+    Room(RoomItemType),
+    Wall(WallItemType),
+}
+
+impl FurnitureType {
+    pub fn id(&self) -> u32 {
+        match *self {
+            FurnitureType::Room(ref item) => item.id,
+            FurnitureType::Wall(ref item) => item.id,
+        }
+    }
+
+    pub fn class_name(&self) -> &str {
+        match *self {
+            FurnitureType::Room(ref item) => &item.class_name,
+            FurnitureType::Wall(ref item) => &item.class_name,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match *self {
+            FurnitureType::Room(ref item) => &item.name,
+            FurnitureType::Wall(ref item) => &item.name,
+        }
+    }
+
+    pub fn revision(&self) -> u32 {
+        match *self {
+            FurnitureType::Room(ref item) => item.revision,
+            FurnitureType::Wall(ref item) => item.revision,
+        }
+    }
+
+    /// Color variant `N` of class names formatted as `classname*N`.
+    pub fn color_index(&self) -> Option<u32> {
+        self.class_name()
+            .splitn(2, '*')
+            .nth(1)
+            .and_then(|index| index.parse().ok())
+    }
+
+    /// Colors of the colorable parts, as hexadecimal RGB. Wall items don't define any.
+    pub fn part_colors(&self) -> &[String] {
+        match *self {
+            FurnitureType::Room(ref item) => &item.part_colors.colors,
+            FurnitureType::Wall(_) => &[],
+        }
+    }
+
+    /// Name of the asset pack, which is the class name without color variant suffix.
+    pub fn asset_name(&self) -> &str {
+        match self.class_name().split('*').next() {
+            Some(value) => value,
+            None => unreachable!(), // "There is always a string value present!"
+        }
+    }
+}
+
+impl FurnitureData {
+    /// Merges room and wall items into one collection keyed by furniture id.
+    ///
+    /// NOTE; The first occurrence of an id wins, room items are listed before wall items.
+    pub fn into_furniture(self) -> BTreeMap<u32, FurnitureType> {
+        let room_furniture = self
+            .room_item_types
+            .items
+            .into_iter()
+            .map(FurnitureType::Room);
+        let wall_furniture = self
+            .wall_item_types
+            .items
+            .into_iter()
+            .map(FurnitureType::Wall);
+
+        room_furniture
+            .chain(wall_furniture)
+            .fold(BTreeMap::new(), |mut acc, item| {
+                acc.entry(item.id()).or_insert(item);
+                acc
+            })
+    }
+}
+
+pub(crate) fn from_xml_reader<R: BufRead>(reader: R) -> Result<FurnitureData, runtime::Error> {
+    quick_xml::de::from_reader(reader).map_err(runtime::Error::FurnitureXml)
+}
+
+pub(crate) fn from_json_reader<R: Read>(reader: R) -> Result<FurnitureData, runtime::Error> {
+    serde_json::from_reader(reader).map_err(runtime::Error::FurnitureJson)
+}
+
+/// Furniture added, removed or updated between two furniture data revisions, matched by id.
+#[derive(Debug, Default)]
+pub(crate) struct FurnitureDiff<'a> {
+    pub added: Vec<&'a FurnitureType>,
+    pub removed: Vec<&'a FurnitureType>,
+    /// Furniture of which the class name or asset revision changed, old and new row.
+    pub updated: Vec<(&'a FurnitureType, &'a FurnitureType)>,
+}
+
+pub(crate) fn diff<'a>(
+    old: &'a BTreeMap<u32, FurnitureType>,
+    new: &'a BTreeMap<u32, FurnitureType>,
+) -> FurnitureDiff<'a> {
+    let mut result = FurnitureDiff::default();
+    for (id, old_item) in old.iter() {
+        match new.get(id) {
+            None => result.removed.push(old_item),
+            Some(new_item)
+                if new_item.class_name() != old_item.class_name()
+                    || new_item.revision() != old_item.revision() =>
+            {
+                result.updated.push((old_item, new_item))
+            }
+            Some(_) => {}
+        }
+    }
+    result.added = new
+        .iter()
+        .filter(|(id, _)| !old.contains_key(id))
+        .map(|(_, item)| item)
+        .collect();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FURNIDATA_XML: &str = include_str!("../tests/fixtures/furnidata.xml");
+    const FURNIDATA_JSON: &str = include_str!("../tests/fixtures/furnidata.json");
+
+    fn dragon_lamp() -> FurnitureType {
+        FurnitureType::Room(RoomItemType {
+            id: 3902,
+            class_name: String::from("rare_dragonlamp*4"),
+            revision: 61856,
+            default_direction: 2,
+            x_dimension: 1,
+            y_dimension: 2,
+            part_colors: PartColors {
+                colors: vec![String::from("#ffffff"), String::from("#3b5a82")],
+            },
+            name: String::from("Blue Dragon Lamp"),
+            description: String::from("Scary and scorching!"),
+            ad_url: String::new(),
+            offer_id: 1234,
+            buyout: true,
+            rent_offer_id: -1,
+            rent_buyout: false,
+            builders_club: false,
+            excluded_dynamic: false,
+            custom_params: String::new(),
+            special_type: 1,
+            can_stand_on: false,
+            can_sit_on: false,
+            can_lay_on: false,
+            furni_line: String::from("rare"),
+            environment: String::new(),
+            rare: true,
+        })
+    }
+
+    fn basic_window() -> FurnitureType {
+        FurnitureType::Wall(WallItemType {
+            id: 4001,
+            class_name: String::from("window_basic"),
+            revision: 45508,
+            name: String::from("Basic Window"),
+            description: String::from("Let the sunshine in"),
+            ad_url: String::from("https://example.com/ad"),
+            offer_id: -1,
+            buyout: false,
+            rent_offer_id: -1,
+            rent_buyout: false,
+            builders_club: true,
+            excluded_dynamic: true,
+            custom_params: String::from("0,1"),
+            special_type: 1,
+            furni_line: String::from("windows"),
+            environment: String::new(),
+            rare: false,
+        })
+    }
+
+    fn assert_fixture(furniture: BTreeMap<u32, FurnitureType>) {
+        assert_eq!(furniture.len(), 2);
+        assert_eq!(furniture[&3902], dragon_lamp());
+        assert_eq!(furniture[&4001], basic_window());
+
+        let lamp = &furniture[&3902];
+        assert_eq!(lamp.asset_name(), "rare_dragonlamp");
+        assert_eq!(lamp.color_index(), Some(4));
+        assert_eq!(lamp.part_colors().len(), 2);
+        assert_eq!(furniture[&4001].color_index(), None);
+        assert!(furniture[&4001].part_colors().is_empty());
+    }
+
+    #[test]
+    fn xml_fixture() {
+        let data = from_xml_reader(FURNIDATA_XML.as_bytes()).unwrap();
+        assert_fixture(data.into_furniture());
+    }
+
+    #[test]
+    fn json_fixture() {
+        let data = from_json_reader(FURNIDATA_JSON.as_bytes()).unwrap();
+        assert_fixture(data.into_furniture());
+    }
+
+    #[test]
+    fn xml_and_json_agree() {
+        let xml = from_xml_reader(FURNIDATA_XML.as_bytes()).unwrap();
+        let json = from_json_reader(FURNIDATA_JSON.as_bytes()).unwrap();
+        assert_eq!(xml, json);
+    }
+}
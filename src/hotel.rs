@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path};
+
+use reqwest::Url;
+use serde::Deserialize;
+
+/// File name of the hotel registry inside the data path.
+pub(crate) const REGISTRY_FILE: &str = "hotels.toml";
+/// Official zones, each is also the top level domain of the hotel.
+const OFFICIAL_ZONES: [&str; 9] = [
+    "com", "de", "fi", "fr", "it", "es", "nl", "com.br", "com.tr",
+];
+/// Asset server shared by all official hotels.
+const OFFICIAL_FURNI_BASE: &str = "http://images.habbo.com/dcr/hof_furni/";
+// WARN; User agent is required because Habbo blocks other asset requests!
+// This header emulates a request from Firefox, but might need tweaking to work in the future.
+const DEFAULT_USER_AGENT: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:74.0) Gecko/20100101 Firefox/74.0";
+/// Keys of a `--hotel` definition.
+const DEFINITION_KEYS: [&str; 5] = ["name", "gamedata", "furni", "user_agent", "header"];
+
+/// Hotel to download furniture from, the name is also the folder inside the data path.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Hotel {
+    pub name: String,
+    /// Base of the `gamedata/...` endpoints.
+    pub gamedata_url: Url,
+    /// Base of the `<revision>/<asset name>.swf` asset packs.
+    pub furni_url: Url,
+    pub user_agent: String,
+    /// Additional headers sent with every request to this hotel.
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Hotel as written in the registry file or in a `--hotel` definition.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HotelEntry {
+    pub name: String,
+    pub gamedata_url: String,
+    pub furni_url: Option<String>,
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    #[serde(rename = "hotel", default)]
+    hotels: Vec<HotelEntry>,
+}
+
+/// Known hotels, by default the official ones.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HotelRegistry {
+    hotels: Vec<Hotel>,
+}
+
+fn parse_url(name: &str, field: &str, value: &str) -> Result<Url, String> {
+    // NOTE; Urls are used as base, without trailing slash the last segment would be replaced.
+    let value = match value.ends_with('/') {
+        true => value.to_string(),
+        false => format!("{}/", value),
+    };
+    Url::parse(&value)
+        .map_err(|error| format!("Hotel {} has invalid {} {}: {}", name, field, value, error))
+}
+
+/// The name is used as folder inside the data path, it must not point anywhere else.
+fn validate_name(name: &str) -> Result<(), String> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        // NOTE; Backslashes only separate on Windows, they are refused everywhere to keep registries
+        // portable.
+        (Some(Component::Normal(component)), None) if component == name && !name.contains('\\') => {
+            Ok(())
+        }
+        _ => Err(format!(
+            "Hotel name `{}` must be a single folder name",
+            name
+        )),
+    }
+}
+
+impl HotelEntry {
+    /// Parses a `--hotel` definition, `;` separated `key=value` pairs. Keys are `name`, `gamedata`,
+    /// `furni`, `user_agent` and `header`, the latter is repeatable and formatted `Name: value`.
+    ///
+    /// NOTE; Only a `;` followed by a known key starts a new pair, values such as user agents and
+    /// cookies keep their own `;`.
+    pub fn parse(definition: &str) -> Result<HotelEntry, String> {
+        let mut pairs: Vec<String> = Vec::new();
+        for part in definition.split(';').filter(|part| !part.trim().is_empty()) {
+            let key = part.split('=').next().unwrap_or_default().trim();
+            let starts_pair = part.contains('=') && DEFINITION_KEYS.contains(&key);
+            match pairs.last_mut() {
+                Some(pair) if !starts_pair => {
+                    pair.push(';');
+                    pair.push_str(part);
+                }
+                _ => pairs.push(part.to_string()),
+            }
+        }
+
+        let mut entry = HotelEntry::default();
+        for pair in pairs.iter() {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| format!("Hotel definition part `{}` has no value", pair))?
+                .trim();
+            match key {
+                "name" => entry.name = value.to_string(),
+                "gamedata" => entry.gamedata_url = value.to_string(),
+                "furni" => entry.furni_url = Some(value.to_string()),
+                "user_agent" => entry.user_agent = Some(value.to_string()),
+                "header" => {
+                    let mut header = value.splitn(2, ':');
+                    let header_name = header.next().unwrap_or_default().trim();
+                    let header_value = header
+                        .next()
+                        .ok_or_else(|| format!("Header `{}` has no value", value))?
+                        .trim();
+                    entry
+                        .headers
+                        .insert(header_name.to_string(), header_value.to_string());
+                }
+                other => return Err(format!("Unknown hotel definition key `{}`", other)),
+            }
+        }
+        if entry.name.is_empty() || entry.gamedata_url.is_empty() {
+            return Err(format!(
+                "Hotel definition `{}` needs at least a name and gamedata url",
+                definition
+            ));
+        }
+        Ok(entry)
+    }
+
+    /// Validates the entry, the asset server and user agent of the official hotels are defaults.
+    pub fn into_hotel(self) -> Result<Hotel, String> {
+        validate_name(&self.name)?;
+        let gamedata_url = parse_url(&self.name, "gamedata url", &self.gamedata_url)?;
+        let furni_url = parse_url(
+            &self.name,
+            "furni url",
+            self.furni_url.as_deref().unwrap_or(OFFICIAL_FURNI_BASE),
+        )?;
+        Ok(Hotel {
+            name: self.name,
+            gamedata_url,
+            furni_url,
+            user_agent: self
+                .user_agent
+                .unwrap_or_else(|| String::from(DEFAULT_USER_AGENT)),
+            headers: self.headers,
+        })
+    }
+}
+
+impl HotelRegistry {
+    pub fn official() -> Self {
+        let hotels = OFFICIAL_ZONES
+            .iter()
+            .map(|zone| Hotel {
+                name: zone.to_string(),
+                gamedata_url: Url::parse(&format!("https://www.habbo.{}/", zone))
+                    .expect("Official gamedata urls are valid"),
+                furni_url: Url::parse(OFFICIAL_FURNI_BASE).expect("Official furni url is valid"),
+                user_agent: String::from(DEFAULT_USER_AGENT),
+                headers: BTreeMap::new(),
+            })
+            .collect();
+        HotelRegistry { hotels }
+    }
+
+    /// Reads the registry file, which replaces the official hotels.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|error| format!("Hotel registry {} unreadable: {}", path.display(), error))?;
+        let file: RegistryFile = toml::from_str(&content)
+            .map_err(|error| format!("Hotel registry {} invalid: {}", path.display(), error))?;
+        let hotels = file
+            .hotels
+            .into_iter()
+            .map(HotelEntry::into_hotel)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(HotelRegistry { hotels })
+    }
+
+    /// Uses the registry file when provided, or when present inside the data path.
+    pub fn discover(registry_file: Option<&Path>, data_path: &Path) -> Result<Self, String> {
+        match registry_file {
+            Some(path) => HotelRegistry::from_file(path),
+            None if data_path.join(REGISTRY_FILE).is_file() => {
+                HotelRegistry::from_file(&data_path.join(REGISTRY_FILE))
+            }
+            None => Ok(HotelRegistry::official()),
+        }
+    }
+
+    /// Adds the entries, replacing registered hotels with the same name.
+    pub fn extend(&mut self, entries: Vec<HotelEntry>) -> Result<(), String> {
+        for entry in entries.into_iter() {
+            let hotel = entry.into_hotel()?;
+            match self
+                .hotels
+                .iter_mut()
+                .find(|other| other.name == hotel.name)
+            {
+                Some(other) => *other = hotel,
+                None => self.hotels.push(hotel),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Hotel> {
+        self.hotels.iter().find(|hotel| hotel.name == name)
+    }
+
+    /// Hotels for the `--hotel` values, each is either a registered name or a definition.
+    ///
+    /// NOTE; Without values every registered hotel is selected.
+    pub fn select(&self, values: &[String]) -> Result<Vec<Hotel>, String> {
+        if values.is_empty() {
+            return Ok(self.hotels.clone());
+        }
+
+        let mut selected: Vec<Hotel> = Vec::with_capacity(values.len());
+        for value in values.iter() {
+            let hotel = match value.contains('=') {
+                true => HotelEntry::parse(value)?.into_hotel()?,
+                false => self
+                    .get(value)
+                    .cloned()
+                    .ok_or_else(|| format!("Unknown hotel `{}`", value))?,
+            };
+            if selected.iter().any(|other| other.name == hotel.name) {
+                return Err(format!("Hotel `{}` is selected twice", hotel.name));
+            }
+            selected.push(hotel);
+        }
+        Ok(selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> HotelEntry {
+        HotelEntry {
+            name: name.to_string(),
+            gamedata_url: String::from("https://hotel.example.com"),
+            ..HotelEntry::default()
+        }
+    }
+
+    #[test]
+    fn folder_names_are_accepted() {
+        for name in ["com", "com.br", "private-hotel"].iter() {
+            assert_eq!(entry(name).into_hotel().unwrap().name, *name);
+        }
+    }
+
+    #[test]
+    fn names_leaving_the_data_path_are_rejected() {
+        let names = [
+            "",
+            ".",
+            "..",
+            "../com",
+            "com/",
+            "nested/hotel",
+            "/tmp",
+            "nested\\hotel",
+        ];
+        for name in names.iter() {
+            assert!(entry(name).into_hotel().is_err(), "{} was accepted", name);
+        }
+        assert!(
+            HotelEntry::parse("name=..;gamedata=https://hotel.example.com")
+                .unwrap()
+                .into_hotel()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn definitions_keep_separators_inside_values() {
+        let entry = HotelEntry::parse(
+            "name=private; gamedata=https://hotel.example.com;furni=https://furni.example.com/;\
+             user_agent=Mozilla/5.0 (X11; Linux x86_64);header=Cookie: a=1; b=2;\
+             header=X-Token: secret;",
+        )
+        .unwrap();
+        assert_eq!(entry.name, "private");
+        assert_eq!(entry.gamedata_url, "https://hotel.example.com");
+        assert_eq!(
+            entry.furni_url.as_deref(),
+            Some("https://furni.example.com/")
+        );
+        assert_eq!(
+            entry.user_agent.as_deref(),
+            Some("Mozilla/5.0 (X11; Linux x86_64)")
+        );
+        let headers: Vec<(&str, &str)> = entry
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(headers, [("Cookie", "a=1; b=2"), ("X-Token", "secret")]);
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        let definitions = [
+            "name=private",
+            "gamedata=https://hotel.example.com",
+            "hotel=private;gamedata=https://hotel.example.com",
+            "name=private;gamedata",
+            "name=private;gamedata=https://hotel.example.com;header=Cookie",
+        ];
+        for definition in definitions.iter() {
+            assert!(
+                HotelEntry::parse(definition).is_err(),
+                "{} was accepted",
+                definition
+            );
+        }
+    }
+
+    #[test]
+    fn selection_resolves_names_and_definitions() {
+        let registry = HotelRegistry::official();
+        let names = |values: &[&str]| -> Result<Vec<String>, String> {
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            registry
+                .select(&values)
+                .map(|hotels| hotels.into_iter().map(|hotel| hotel.name).collect())
+        };
+
+        assert_eq!(names(&[]).unwrap().len(), OFFICIAL_ZONES.len());
+        assert_eq!(
+            names(&["nl", "name=private;gamedata=https://hotel.example.com"]).unwrap(),
+            ["nl", "private"]
+        );
+        assert!(names(&["moon"]).is_err());
+        assert!(names(&["nl", "nl"]).is_err());
+        assert!(names(&["com", "name=com;gamedata=https://hotel.example.com"]).is_err());
+    }
+
+    #[test]
+    fn registry_files_replace_official_hotels() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join(REGISTRY_FILE);
+
+        fs::write(
+            &path,
+            "[[hotel]]\nname = \"private\"\ngamedata_url = \"https://hotel.example.com\"\n",
+        )
+        .unwrap();
+        let registry = HotelRegistry::discover(None, folder.path()).unwrap();
+        assert!(registry.get("private").is_some());
+        assert!(registry.get("com").is_none());
+
+        fs::write(
+            &path,
+            "[[hotel]]\nname = \"private\"\ngamedata = \"https://hotel.example.com\"\n",
+        )
+        .unwrap();
+        assert!(HotelRegistry::from_file(&path).is_err());
+        fs::write(&path, "hotels = []\n").unwrap();
+        assert!(HotelRegistry::from_file(&path).is_err());
+    }
+}
@@ -0,0 +1,26 @@
+//! Typed model of the `<asset name>_index.xml` document embedded in each furniture asset pack.
+
+use std::io::BufRead;
+
+use quick_xml::de::DeError;
+use serde::{Deserialize, Serialize};
+
+/// Names the visualization and logic implementations the client uses for the furniture.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "object")]
+pub struct IndexData {
+    #[serde(rename = "type", default)]
+    pub furniture_type: String,
+    #[serde(default)]
+    pub visualization: String,
+    #[serde(default)]
+    pub logic: String,
+}
+
+pub fn from_reader<R: BufRead>(reader: R) -> Result<IndexData, DeError> {
+    quick_xml::de::from_reader(reader)
+}
+
+pub fn from_str(document: &str) -> Result<IndexData, DeError> {
+    quick_xml::de::from_str(document)
+}
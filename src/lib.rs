@@ -0,0 +1,9 @@
+//! Data models of the documents embedded in furniture asset packs.
+//!
+//! The extractor binary uses these to interpret the extracted files, other tooling can depend on them to
+//! read the extraction output.
+
+pub mod assets;
+pub mod index;
+pub mod logic;
+pub mod visualization;
@@ -0,0 +1,222 @@
+//! Typed model of the `<asset name>_logic.xml` document embedded in each furniture asset pack.
+
+use std::io::BufRead;
+
+use quick_xml::de::DeError;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "objectData")]
+pub struct ObjectData {
+    #[serde(rename = "type", default)]
+    pub furniture_type: String,
+    #[serde(default)]
+    pub model: Model,
+    pub action: Option<Action>,
+    pub credits: Option<Credits>,
+    pub sound: Option<Sound>,
+    #[serde(rename = "particlesystems")]
+    pub particle_systems: Option<ParticleSystems>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Model {
+    pub dimensions: Option<Dimensions>,
+    #[serde(default)]
+    pub directions: Directions,
+}
+
+/// Footprint of the furniture in tiles, `z` is the stacking height.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Dimensions {
+    pub x: u32,
+    pub y: u32,
+    pub z: Option<f32>,
+    #[serde(rename = "centerZ")]
+    pub center_z: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Directions {
+    #[serde(rename = "direction", default)]
+    pub items: Vec<Direction>,
+}
+
+/// Allowed rotation of the furniture, in degrees.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Direction {
+    pub id: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    pub link: Option<String>,
+    #[serde(rename = "startState")]
+    pub start_state: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Credits {
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sound {
+    #[serde(rename = "sample", default)]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub id: u32,
+    #[serde(rename = "nodeId")]
+    pub node_id: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParticleSystems {
+    #[serde(rename = "particlesystem", default)]
+    pub items: Vec<ParticleSystem>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParticleSystem {
+    pub size: u32,
+    pub canvas_id: Option<i32>,
+    pub offset_y: Option<i32>,
+    pub blend: Option<f32>,
+    pub bgcolor: Option<String>,
+    #[serde(rename = "emitter", default)]
+    pub emitters: Vec<Emitter>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Emitter {
+    pub id: u32,
+    pub name: Option<String>,
+    #[serde(rename = "spriteId")]
+    pub sprite_id: Option<i32>,
+    #[serde(rename = "maxNumParticles")]
+    pub max_num_particles: Option<u32>,
+    #[serde(rename = "particlesPerFrame")]
+    pub particles_per_frame: Option<u32>,
+    #[serde(rename = "burstPulse")]
+    pub burst_pulse: Option<u32>,
+    #[serde(rename = "fuseTime")]
+    pub fuse_time: Option<u32>,
+    pub simulation: Option<Simulation>,
+    #[serde(default)]
+    pub particles: Particles,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Simulation {
+    pub force: Option<f32>,
+    pub direction: Option<f32>,
+    pub gravity: Option<f32>,
+    #[serde(rename = "airFriction")]
+    pub air_friction: Option<f32>,
+    pub shape: Option<String>,
+    pub energy: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Particles {
+    #[serde(rename = "particle", default)]
+    pub items: Vec<Particle>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Particle {
+    #[serde(rename = "lifeTime")]
+    pub life_time: Option<u32>,
+    #[serde(rename = "isEmitter")]
+    pub is_emitter: Option<bool>,
+    pub fade: Option<bool>,
+    #[serde(rename = "frame", default)]
+    pub frames: Vec<ParticleFrame>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParticleFrame {
+    pub name: String,
+}
+
+impl Direction {
+    /// Index of the direction as used by visualization data, one step per 45 degrees.
+    pub fn index(&self) -> u32 {
+        (self.id % 360) / 45
+    }
+}
+
+impl ObjectData {
+    pub fn dimensions(&self) -> Option<&Dimensions> {
+        self.model.dimensions.as_ref()
+    }
+
+    pub fn direction_indices(&self) -> Vec<u32> {
+        self.model
+            .directions
+            .items
+            .iter()
+            .map(Direction::index)
+            .collect()
+    }
+}
+
+pub fn from_reader<R: BufRead>(reader: R) -> Result<ObjectData, DeError> {
+    quick_xml::de::from_reader(reader)
+}
+
+pub fn from_str(document: &str) -> Result<ObjectData, DeError> {
+    quick_xml::de::from_str(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/rare_dragonlamp_logic.xml");
+
+    #[test]
+    fn fixture_is_parsed() {
+        let data = from_str(FIXTURE).unwrap();
+        assert_eq!(data.furniture_type, "rare_dragonlamp");
+        assert_eq!(
+            data.dimensions(),
+            Some(&Dimensions {
+                x: 1,
+                y: 2,
+                z: Some(1.5),
+                center_z: Some(0.5),
+            })
+        );
+        assert_eq!(data.direction_indices(), vec![2, 4]);
+        assert_eq!(data.action.as_ref().unwrap().start_state, Some(1));
+        assert_eq!(data.credits.as_ref().unwrap().value, "5");
+        assert_eq!(data.sound.as_ref().unwrap().samples[0].node_id, Some(0));
+
+        let system = &data.particle_systems.as_ref().unwrap().items[0];
+        assert_eq!((system.size, system.canvas_id), (64, Some(1)));
+        let emitter = &system.emitters[0];
+        assert_eq!(emitter.name.as_deref(), Some("sparks"));
+        assert_eq!(
+            emitter.simulation.as_ref().unwrap().shape.as_deref(),
+            Some("cone")
+        );
+        let particle = &emitter.particles.items[0];
+        assert_eq!(
+            (particle.is_emitter, particle.fade),
+            (Some(false), Some(true))
+        );
+        assert_eq!(particle.frames[0].name, "rare_dragonlamp_64_c_0_0");
+    }
+
+    #[test]
+    fn fixture_round_trips_through_json() {
+        let data = from_str(FIXTURE).unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        let parsed: ObjectData = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, data);
+    }
+}
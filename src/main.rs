@@ -1,63 +1,26 @@
-use std::collections::BTreeMap;
-use std::error::Error;
-use std::fmt;
-use std::fs;
-use std::fs::{File, OpenOptions};
-use std::io;
-use std::io::{prelude::*, BufReader, Seek, SeekFrom};
-use std::iter::IntoIterator;
-use std::path::Path;
-
-
-use flate2::read::ZlibDecoder;
-use glob::glob;
-use quick_xml::de::{from_reader, DeError};
-use reqwest::{header, StatusCode, Url};
-use serde::Deserialize;
-// use swf_parser::swf_types::{ImageType, Tag};
-// use swf_parser::{parse_swf, SwfParseError};
-use png;
-use swf::*;
-
-#[derive(Debug, Deserialize, PartialEq)]
-#[serde(rename = "furnidata")]
-struct FurnitureData {
-    #[serde(rename = "roomitemtypes")]
-    room_item_types: RoomItemTypes,
-    #[serde(rename = "wallitemtypes")]
-    wall_item_types: WallItemTypes,
-}
-
-#[derive(Debug, Deserialize, PartialEq)]
-struct RoomItemTypes {
-    #[serde(rename = "furnitype")]
-    items: Vec<FurnitureType>,
-}
-
-#[derive(Debug, Deserialize, PartialEq)]
-struct WallItemTypes {
-    #[serde(rename = "furnitype")]
-    items: Vec<FurnitureType>,
-}
-
-#[derive(Debug, Deserialize, PartialEq)]
-struct FurnitureType {
-    id: u32,
-    #[serde(rename = "classname")]
-    class_name: String,
-    name: String,
-    description: String,
-    revision: u32,
-    #[serde(rename = "canstandon")]
-    can_stand_on: Option<bool>,
-}
-
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+mod animation;
 mod cli;
+mod config;
 mod error;
 mod runtime;
 mod asset_metadata;
 mod asset_extraction;
+mod atlas;
+mod bitmap;
+mod cache;
+mod furni_document;
+mod furnidata;
+mod hotel;
+mod nitro;
+mod render;
+mod swf_container;
+#[cfg(test)]
+mod test_server;
+mod throttle;
 
 use slog::Drain;
 use slog_async;
@@ -96,317 +59,348 @@ fn main() -> Result<(), error::ExtractorError> {
         slog::Logger::root(drain.fuse(), slog::o!())
     };
 
-    let io_thread_count = 50;
-    let cpu_thread_count = 4;
     let options = cli::get_cli()?;
 
     slog::info!(root_logger, "Program initialized");
     slog::error!(root_logger, "Program initialized");
 
-    options.zones
-        .clone()
-        .with_threads(io_thread_count)
-        .map(|x| {
-            asset_metadata::prepare_folders();
-            asset_metadata::download_index_data();
-        })
-        .with_threads(io_thread_count)
-        .map(|x| asset_extraction::download_asset_packs())
-        .with_threads(cpu_thread_count)
-        .map(|x| asset_extraction::extract_asset_packs());
-
-    // let DATA_DIR = "./data";
-    // let FURNI_FILE = "./data/furniture_data.xml";
-    // let FURNI_BASE_PATH = "./data/furniture/";
-    // let FURNI_EXTRACTED_PATH = "./data/extracted";
-    // fs::create_dir_all(DATA_DIR)?;
-    // fs::create_dir_all(FURNI_BASE_PATH)?;
-    // fs::create_dir_all(FURNI_EXTRACTED_PATH)?;
-
-    // println!("I require furniture data, either a local path or URL is fine!");
-    // let mut furni_string = String::new();
-    // io::stdin().read_line(&mut furni_string)?;
-    // // WARN; String includes the newline!
-    // let furni_string = furni_string.trim().trim_matches(|c| c == '\'' || c == '"');
-
-    // let furni_file_open_result = OpenOptions::new().read(true).open(furni_string);
-    // let furni_file = match furni_file_open_result {
-    //     Ok(file) => file,
-    //     Err(open_error) => {
-    //         println!(
-    //             "[DBG] Local file couldn't be opened due to error: {:?}\nAttempting download",
-    //             open_error
-    //         );
-
-    //         let mut response = http_client.get(furni_string).send()?.error_for_status()?;
-    //         if !response.status().is_success() {
-    //             Err(BobbaError::FurniDownload(response.status()))?;
-    //         }
-
-    //         let mut furni_file = OpenOptions::new()
-    //             .read(true)
-    //             .write(true)
-    //             .create(true)
-    //             .truncate(true)
-    //             .open(FURNI_FILE)?;
-
-    //         if response.copy_to(&mut furni_file)? == 0 {
-    //             Err(BobbaError::FurniDownload(StatusCode::NO_CONTENT))?;
-    //         }
-    //         furni_file.seek(SeekFrom::Start(0))?;
-    //         println!("Furni file downloaded to {}", FURNI_FILE);
-    //         furni_file
-    //     }
-    // };
-
-    // let furniture_structures: FurnitureData = from_reader(BufReader::new(furni_file))?;
-    // let room_furniture = furniture_structures.room_item_types.items.into_iter();
-    // let wall_furniture = furniture_structures.wall_item_types.items.into_iter();
-
-    // let furniture = room_furniture.chain(wall_furniture).fold(
-    //     BTreeMap::<u32, FurnitureType>::new(),
-    //     |mut acc, item| {
-    //         acc.entry(item.id).or_insert(item);
-    //         acc
-    //     },
-    // );
-
-    // let MEDIA_URL_BASE = Url::parse("http://images.habbo.com/dcr/hof_furni/")?;
-    // for (furni_id, furni_struct) in furniture {
-    //     if furni_struct.revision == 0 {
-    //         println!(
-    //             "SKIP; Furniture `{}`: specific revision {}!",
-    //             furni_struct.name, furni_struct.revision,
-    //         );
-    //         continue;
-    //     }
-
-    //     let revision = furni_struct.revision.to_string();
-    //     let asset_name = match furni_struct.class_name.split('*').next() {
-    //         Some(value) => value,
-    //         None => unreachable!(), // "There is always a string value present!"
-    //     };
-    //     let furni_file_name = format!("{base_name}.swf", base_name = asset_name);
-    //     let mut furni_url = MEDIA_URL_BASE.clone();
-    //     furni_url
-    //         .path_segments_mut()
-    //         .expect("No static base path!")
-    //         .extend(&[&revision, &furni_file_name]);
-    //     let furni_local_path = Path::new(FURNI_BASE_PATH).join(&furni_file_name);
-    //     if furni_local_path.exists() {
-    //         println!(
-    //             "SKIP; Furniture `{}`: Already downloaded!",
-    //             furni_struct.name
-    //         );
-    //         continue;
-    //     }
-
-    //     let mut response = http_client.get(furni_url).send()?;
-    //     if !response.status().is_success() {
-    //         // Err(BobbaError::FurniDownload(response.status()))?;
-    //         println!("FAIL; Furniture `{}`", furni_struct.name);
-    //     }
-
-    //     let mut furni_file = OpenOptions::new()
-    //         .write(true)
-    //         .create(true)
-    //         .truncate(true)
-    //         .open(furni_local_path)?;
-
-    //     if response.copy_to(&mut furni_file)? == 0 {
-    //         // Err(BobbaError::FurniDownload(StatusCode::NO_CONTENT))?;
-    //         println!("FAIL; Furniture `{}`", furni_struct.name);
-    //     }
-
-    //     println!("OK; Furniture `{}`", furni_struct.name);
-    // }
-
-    // let swf_blob_glob = Path::new(FURNI_BASE_PATH).join("**/*.swf");
-    // let swf_blobs_glob_str = swf_blob_glob.to_str().expect("Invalid string characters!");
-    // println!("Extracting from all {:?}", swf_blobs_glob_str);
-    // let swf_blobs_iterator = glob(swf_blobs_glob_str).expect("Invalid Glob!");
-    // for swf_blob_result in swf_blobs_iterator {
-    //     let swf_blob_path = swf_blob_result?;
-    //     let swf_basename = swf_blob_path.file_stem().expect("Path should have a stem!");
-    //     let swf_blob = fs::read(&swf_blob_path)?;
-
-    //     let mut asset_map = BTreeMap::new();
-    //     let extracted_asset_path = Path::new(FURNI_EXTRACTED_PATH).join(&swf_basename);
-    //     fs::create_dir_all(&extracted_asset_path)?;
-
-    //     // let swf_movie = parse_swf(&swf_blob).map_err(BobbaError::SwfParseError)?;
-    //     let swf_movie = match read_swf(&swf_blob[..]) {
-    //         Ok(result) => result,
-    //         Err(error) => {
-    //             println!("Error reading SWF file `{:?}`: {:}", swf_blob_path, error);
-    //             continue;
-    //         }
-    //     };
-    //     for tag in swf_movie.tags.iter() {
-    //         match tag {
-    //             Tag::SymbolClass(symbol_class) => {
-    //                 for asset in symbol_class.iter() {
-    //                     // NOTE; len() + 1 because the trimming ends with an additional underscore
-    //                     let trimmed_asset_name: String = asset
-    //                         .class_name
-    //                         .chars()
-    //                         .skip(swf_basename.len() + 1)
-    //                         .collect();
-    //                     asset_map
-    //                         .entry(asset.id as u16)
-    //                         .or_insert(trimmed_asset_name);
-    //                     println!(
-    //                         "Detected file name: {} at idx {}, for furniture `{:?}`",
-    //                         asset.class_name, asset.id, swf_basename
-    //                     );
-    //                 }
-    //             }
-    //             _ => continue,
-    //         }
-    //     }
+    let data_path = options.data_path;
+    let io_thread_count = options.io_thread_count;
+    let cpu_thread_count = options.cpu_thread_count;
+    let host_connection_limit = options.host_connection_limit;
+    slog::debug!(root_logger, "Worker pools"; "io_threads" => io_thread_count,
+        "cpu_threads" => cpu_thread_count, "host_connections" => host_connection_limit);
+
+    match options.command {
+        cli::Command::Download(command) => download(
+            data_path,
+            command,
+            io_thread_count,
+            host_connection_limit,
+            &root_logger,
+        ),
+        cli::Command::Extract(command) => {
+            extract(&data_path, command, cpu_thread_count, &root_logger)
+        }
+        cli::Command::Render(command) => {
+            render(&data_path, command, cpu_thread_count, &root_logger)
+        }
+        cli::Command::Inspect(command) => inspect(&data_path, command, &root_logger),
+        cli::Command::Diff(command) => diff(&data_path, command, &root_logger),
+    }
+}
 
-    //     for tag in swf_movie.tags.iter() {
-    //         match tag {
-    //             // NOTE; Tag order is not defined! We might have to split this in two passes!
-    //             Tag::DefineBinaryData {
-    //                 id: asset_id,
-    //                 data: blob,
-    //             } => {
-    //                 let asset_id = *asset_id as u16;
-    //                 let blob_file_stem = asset_map.get(&asset_id).ok_or({
-    //                     let max_data = std::cmp::min(blob.len(), 100);
-    //                     let err_string = format!(
-    //                         "BINARY DATA: Asset id ({}) {} was not registered! Bytes: {:x?}",
-    //                         asset_id,
-    //                         0, // blob_stem_id
-    //                         &blob[0..max_data]
-    //                     );
-    //                     BobbaError::Other(err_string)
-    //                 })?;
+/// Checks a pinned build was downloaded by an earlier run, a pinned build is never discovered.
+fn verify_pin(data_path: &Path, source: &cli::SourceOptions) -> Result<(), error::ExtractorError> {
+    if let Some(ref pin) = source.version_pin {
+        for hotel in source.hotels.iter() {
+            let runtime = runtime::RuntimeData::pinned(data_path.join(&hotel.name), pin.clone());
+            if !asset_metadata::has_index_data(&runtime) {
+                return Err(error::ExtractorError::UnavailableRevision(
+                    pin.clone(),
+                    runtime.get_data_path(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
 
-    //                 let blob_file_name = format!("{}.xml", blob_file_stem);
-    //                 let blob_destination_file = extracted_asset_path.join(blob_file_name);
-    //                 if blob_destination_file.exists() {
-    //                     println!("Skipping");
-    //                     break;
-    //                 }
-    //                 fs::write(blob_destination_file, &blob)?;
-    //             }
-    //             Tag::DefineBitsLossless(bitmap) => {
-    //                 let asset_id = bitmap.id as u16;
-    //                 let mut data_buffer = &bitmap.data;
-    //                 let blob_file_stem = asset_map.get(&asset_id).ok_or({
-    //                     let max_data = std::cmp::min(bitmap.data.len(), 100);
-    //                     let err_string = format!(
-    //                         "BITMAP: Asset id ({}) {} was not registered! Bytes: {:x?}",
-    //                         asset_id,
-    //                         0, // blob_stem_id
-    //                         &bitmap.data[0..max_data]
-    //                     );
-    //                     BobbaError::Other(err_string)
-    //                 })?;
+/// Collects the asset packs of every hotel from furniture data on disk.
+///
+/// NOTE; Hotels without usable furniture data are logged and skipped.
+fn local_asset_packs(
+    data_path: &Path,
+    source: &cli::SourceOptions,
+    logger: &slog::Logger,
+) -> Result<Vec<asset_extraction::AssetPack>, error::ExtractorError> {
+    verify_pin(data_path, source)?;
+
+    let mut packs = Vec::new();
+    for hotel in source.hotels.iter() {
+        let base_data_path = data_path.join(&hotel.name);
+        let revision = match source.version_pin {
+            Some(ref pin) => Ok(runtime::RuntimeData::pinned(base_data_path, pin.clone())),
+            None => asset_metadata::cached_index_data(&hotel.gamedata_url, base_data_path),
+        };
+        let hotel_packs = revision.and_then(|runtime| {
+            let furniture = asset_metadata::load_index_data(&runtime)?.into_furniture();
+            asset_extraction::asset_packs(&runtime, hotel, &furniture)
+        });
+        match hotel_packs {
+            Ok(hotel_packs) => packs.extend(hotel_packs),
+            Err(error) => slog::error!(logger, "Index data unavailable";
+                "hotel" => &hotel.name, "error" => %error),
+        }
+    }
+    Ok(packs)
+}
 
-    //                 let mut decompressed_image_buffer = Vec::new();
-    //                 let mut decoder = ZlibDecoder::new(&bitmap.data[..]);
-    //                 match decoder.read_to_end(&mut decompressed_image_buffer) {
-    //                     Result::Ok(_) => {
-    //                         let buffer_copy: Vec<u8> = decompressed_image_buffer[..]
-    //                             .chunks(4)
-    //                             // NOTE; There is a missing IntoIterator implementation for slice/array that prevents
-    //                             // us from simply calling [..].into_iter().
-    //                             // We require an intermediate Vector, otherwise we're stuck with Iterator<Item=&u8>
-    //                             .flat_map(|data| vec![data[1], data[2], data[3], data[0]])
-    //                             .collect();
-    //                         decompressed_image_buffer.clear();
-    //                         {
-    //                             let mut png_encoder = png::Encoder::new(
-    //                                 &mut decompressed_image_buffer,
-    //                                 bitmap.width as u32,
-    //                                 bitmap.height as u32,
-    //                             );
-    //                             png_encoder.set_color({
-    //                                 match bitmap.format {
-    //                                     BitmapFormat::Rgb32 => png::ColorType::RGBA,
-    //                                     _ => unimplemented!("Unknown bit depth!"),
-    //                                 }
-    //                             });
-    //                             png_encoder.set_depth({
-    //                                 match bitmap.format {
-    //                                     BitmapFormat::Rgb32 => png::BitDepth::Eight,
-    //                                     _ => unimplemented!("Unknown bit depth!"),
-    //                                 }
-    //                             });
-    //                             let mut png_writer = png_encoder.write_header()?;
-    //                             png_writer.write_image_data(&buffer_copy)?;
-    //                         }
-    //                         data_buffer = &decompressed_image_buffer;
-    //                     }
-    //                     Result::Err(err) => {
-    //                         println!("ERROR: skipping");
-    //                         continue;
-    //                     }
-    //                 };
+fn download(
+    data_path: PathBuf,
+    options: cli::DownloadOptions,
+    io_thread_count: usize,
+    host_connection_limit: usize,
+    root_logger: &slog::Logger,
+) -> Result<(), error::ExtractorError> {
+    verify_pin(&data_path, &options.source)?;
+    let options = Arc::new(options);
+    // NOTE; Every hotel gets its own client, they differ in user agent and headers.
+    let web_clients = options
+        .source
+        .hotels
+        .iter()
+        .map(|hotel| {
+            Ok((
+                hotel.name.clone(),
+                runtime::RuntimeData::create_web_client(hotel)?,
+            ))
+        })
+        .collect::<Result<HashMap<_, _>, runtime::Error>>()
+        .map_err(error::ExtractorError::Runtime)?;
+    let web_clients = Arc::new(web_clients);
+
+    let metadata_logger = root_logger.clone();
+    let metadata_options = options.clone();
+    let metadata_clients = web_clients.clone();
+    let revisions = options
+        .source
+        .hotels
+        .clone()
+        .with_threads(io_thread_count)
+        .map(move |hotel| -> Result<_, runtime::Error> {
+            let logger = metadata_logger.new(slog::o!("hotel" => hotel.name.clone()));
+            let base_data_path = data_path.join(&hotel.name);
+            let runtime = match metadata_options.source.version_pin {
+                Some(ref pin) => runtime::RuntimeData::pinned(base_data_path, pin.clone()),
+                None => asset_metadata::download_index_data(
+                    &metadata_clients[&hotel.name],
+                    &hotel.gamedata_url,
+                    base_data_path,
+                    metadata_options.furnidata_format,
+                    metadata_options.cache_time,
+                    &logger,
+                )?,
+            };
+            let furniture = asset_metadata::load_index_data(&runtime)?.into_furniture();
+            slog::info!(logger, "Furniture data parsed"; "items" => furniture.len());
+            Ok((runtime, hotel, furniture))
+        });
+
+    let index_logger = root_logger.clone();
+    let packs = revisions.filter_map(move |revision| {
+        let packs = revision.and_then(|(runtime, hotel, furniture)| {
+            asset_extraction::asset_packs(&runtime, &hotel, &furniture)
+        });
+        match packs {
+            Ok(packs) => Some(packs),
+            Err(error) => {
+                slog::error!(index_logger, "Index data unavailable"; "error" => %error);
+                None
+            }
+        }
+    });
+
+    if options.metadata_only {
+        let pack_count: usize = packs.map(|packs| packs.len()).sum();
+        slog::info!(root_logger, "Furniture data refreshed"; "asset_packs" => pack_count);
+        return Ok(());
+    }
+
+    let download_logger = root_logger.clone();
+    let download_options = options.clone();
+    let limiter = Arc::new(throttle::HostLimiter::new(host_connection_limit));
+    let retry_policy = asset_extraction::RetryPolicy::default();
+    let downloads = packs
+        .flatten()
+        .with_threads(io_thread_count)
+        .map(move |pack| {
+            asset_extraction::download_asset_pack(
+                &web_clients[&pack.hotel],
+                &pack,
+                &limiter,
+                &retry_policy,
+                download_options.cache_time,
+                &download_logger,
+            )
+        });
+
+    let mut download_report = asset_extraction::DownloadReport::default();
+    for status in downloads {
+        download_report.record(status);
+    }
+    slog::info!(root_logger, "Asset packs processed"; "report" => %download_report);
+    Ok(())
+}
 
-    //                 let blob_file_name = format!("{}.{}", blob_file_stem, "png");
-    //                 let blob_destination_file = extracted_asset_path.join(blob_file_name);
-    //                 if blob_destination_file.exists() {
-    //                     println!("Skipping");
-    //                     break;
-    //                 }
-    //                 fs::write(blob_destination_file, &data_buffer)?;
-    //             }
-    //             // Tag::DefineBitmap(bitmap) => {
-    //             //     let asset_id = bitmap.id;
-    //             //     let mut data_buffer = &bitmap.data;
-    //             //     let blob_file_stem = asset_map.get(&asset_id).ok_or({
-    //             //         let err_string = format!(
-    //             //             "BITMAP: Asset id ({}) {} was not registered! Bytes: {:x?}",
-    //             //             asset_id,
-    //             //             0, // blob_stem_id
-    //             //             &bitmap.data[0..=100]
-    //             //         );
-    //             //         BobbaError::Other(err_string)
-    //             //     })?;
+fn extract(
+    data_path: &Path,
+    options: cli::ExtractOptions,
+    cpu_thread_count: usize,
+    root_logger: &slog::Logger,
+) -> Result<(), error::ExtractorError> {
+    let packs = local_asset_packs(data_path, &options.source, root_logger)?;
+    let extraction_logger = root_logger.clone();
+    let extraction_options = asset_extraction::ExtractionOptions {
+        format: options.output_format,
+        atlas_trim: options.atlas_trim,
+        atlas_max_size: options.atlas_max_size,
+    };
+    let extractions = packs
+        .into_iter()
+        .filter(|pack| pack.swf_path.is_file())
+        .with_threads(cpu_thread_count)
+        .map(move |pack| {
+            asset_extraction::extract_asset_packs(&pack, &extraction_options, &extraction_logger)
+        });
+
+    let mut extracted_count = 0usize;
+    let mut extraction_failures = 0usize;
+    for extraction in extractions {
+        match extraction {
+            Ok(_) => extracted_count += 1,
+            Err(error) => {
+                extraction_failures += 1;
+                slog::error!(root_logger, "Extraction failed"; "error" => %error);
+            }
+        }
+    }
+    slog::info!(root_logger, "Asset packs extracted";
+        "extracted" => extracted_count, "failed" => extraction_failures);
+    Ok(())
+}
 
-    //             //     let mut decoded_image_buffer = Vec::new();
-    //             //     let blob_file_extension = match bitmap.media_type {
-    //             //         ImageType::Png => "png",
-    //             //         ImageType::SwfLossless2 => {
-    //             //             let mut decoder = ZlibDecoder::new(&bitmap.data[..]);
-    //             //             match decoder.read(&mut decoded_image_buffer) {
-    //             //                 Result::Ok(_) => {}
-    //             //                 Result::Err(err) => {
-    //             //                     println!("ERROR: skipping");
-    //             //                     continue;
-    //             //                 }
-    //             //             }
-    //             //             data_buffer = &decoded_image_buffer;
-    //             //             "unknown"
-    //             //         }
-    //             //         // WARN; We have no access to the extended headers!
-    //             //         // We're making a decoding guess here!
-    //             //         x => {
-    //             //             println!(
-    //             //                 "BITMAP: Asset `{}`, unimplemented variant {:?}",
-    //             //                 blob_file_stem, x
-    //             //             );
-    //             //             "unknown"
-    //             //         }
-    //             //         // unimplemented!(
-    //             //         // "BITMAP: Index {}, Unknown media type {:?}! Bytes: {:x?}",
-    //             //         // asset_id,
-    //             //         // bitmap.media_type,
-    //             //         // &bitmap.data[0..=100]
-    //             //     };
+fn render(
+    data_path: &Path,
+    options: cli::RenderOptions,
+    cpu_thread_count: usize,
+    root_logger: &slog::Logger,
+) -> Result<(), error::ExtractorError> {
+    let packs = local_asset_packs(data_path, &options.source, root_logger)?;
+    let render_logger = root_logger.clone();
+    let animation_options = match options.animate {
+        true => Some(animation::AnimationOptions {
+            gif: options.gif,
+            directions: options.directions,
+            states: options.states,
+        }),
+        false => None,
+    };
+    let renders = packs
+        .into_iter()
+        .filter(|pack| pack.extract_path().is_dir())
+        .with_threads(cpu_thread_count)
+        .map(move |pack| -> Result<_, runtime::Error> {
+            render::write_previews(&pack, &render_logger)?;
+            if let Some(ref animation_options) = animation_options {
+                animation::write_animations(&pack, animation_options, &render_logger)?;
+            }
+            Ok(())
+        });
+
+    let mut rendered_count = 0usize;
+    let mut render_failures = 0usize;
+    for rendered in renders {
+        match rendered {
+            Ok(_) => rendered_count += 1,
+            Err(error) => {
+                render_failures += 1;
+                slog::error!(root_logger, "Rendering failed"; "error" => %error);
+            }
+        }
+    }
+    slog::info!(root_logger, "Asset packs rendered";
+        "rendered" => rendered_count, "failed" => render_failures);
+    Ok(())
+}
 
-    //             //     let blob_file_name = format!("{}.{}", blob_file_stem, blob_file_extension);
-    //             //     let blob_destination_file = extracted_asset_path.join(blob_file_name);
-    //             //     fs::write(blob_destination_file, &data_buffer)?;
-    //             // }
-    //             _ => continue,
-    //         }
-    //     }
-    // }
+fn inspect(
+    data_path: &Path,
+    options: cli::InspectOptions,
+    logger: &slog::Logger,
+) -> Result<(), error::ExtractorError> {
+    let data = std::fs::read(&options.path)
+        .map_err(|error| error::ExtractorError::Runtime(runtime::Error::Io(error)))?;
+    let stem = options.path.file_stem().unwrap_or_default();
+
+    if options
+        .path
+        .extension()
+        .map_or(false, |extension| extension == "nitro")
+    {
+        let bundle = nitro::NitroBundle::from_bytes(&data).map_err(|error| {
+            error::ExtractorError::Runtime(runtime::Error::InvalidBundle(
+                options.path.clone(),
+                error.to_string(),
+            ))
+        })?;
+        for (name, content) in bundle.files.iter() {
+            slog::info!(logger, "Bundle file"; "name" => name, "bytes" => content.len());
+        }
+
+        if options.unpack {
+            let destination = data_path.join("unpacked").join(stem);
+            nitro::unpack(&options.path, &destination, options.slice, logger)
+                .map_err(error::ExtractorError::Runtime)?;
+            slog::info!(logger, "Nitro bundle unpacked"; "destination" => %destination.display());
+        }
+        return Ok(());
+    }
+
+    let swf_error = |reason: String| {
+        error::ExtractorError::Runtime(runtime::Error::SwfParse(options.path.clone(), reason))
+    };
+    let header = swf_container::read_header(&data).map_err(swf_error)?;
+    slog::info!(logger, "SWF header"; "compression" => ?header.compression,
+        "version" => header.version, "length" => header.uncompressed_length);
+    let movie_data = swf_container::decompress(&data).map_err(swf_error)?;
+    let movie = swf::read_swf(&movie_data[..]).map_err(|error| swf_error(error.to_string()))?;
+    let symbols = asset_extraction::collect_symbols(&movie.tags, &stem.to_string_lossy());
+    slog::info!(logger, "SWF movie"; "tags" => movie.tags.len(), "symbols" => symbols.len());
+    for (id, name) in symbols.iter() {
+        slog::info!(logger, "Symbol"; "id" => id, "name" => name);
+    }
+    Ok(())
+}
 
+fn diff(
+    data_path: &Path,
+    options: cli::DiffOptions,
+    logger: &slog::Logger,
+) -> Result<(), error::ExtractorError> {
+    for hotel in options.hotels.iter() {
+        let logger = logger.new(slog::o!("hotel" => hotel.name.clone()));
+        let load = |revision: &str| {
+            let runtime =
+                runtime::RuntimeData::pinned(data_path.join(&hotel.name), revision.to_string());
+            if !asset_metadata::has_index_data(&runtime) {
+                return Err(error::ExtractorError::UnavailableRevision(
+                    revision.to_string(),
+                    runtime.get_data_path(),
+                ));
+            }
+            asset_metadata::load_index_data(&runtime)
+                .map(|data| data.into_furniture())
+                .map_err(error::ExtractorError::Runtime)
+        };
+        let old = load(&options.from)?;
+        let new = load(&options.to)?;
+
+        let changes = furnidata::diff(&old, &new);
+        for item in changes.added.iter() {
+            slog::info!(logger, "Added"; "id" => item.id(), "class" => item.class_name());
+        }
+        for item in changes.removed.iter() {
+            slog::info!(logger, "Removed"; "id" => item.id(), "class" => item.class_name());
+        }
+        for (old_item, new_item) in changes.updated.iter() {
+            slog::info!(logger, "Updated"; "id" => new_item.id(),
+                "class" => new_item.class_name(), "old_class" => old_item.class_name(),
+                "revision" => new_item.revision(), "old_revision" => old_item.revision());
+        }
+        slog::info!(logger, "Furniture data compared";
+            "added" => changes.added.len(), "removed" => changes.removed.len(),
+            "updated" => changes.updated.len());
+    }
     Ok(())
 }
@@ -0,0 +1,799 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use bobba_asset_extractor::assets::{self, AssetList, AssetManifest, ExtractedAsset};
+use bobba_asset_extractor::index::IndexData;
+use bobba_asset_extractor::logic::{self, ObjectData};
+use bobba_asset_extractor::visualization::{self, Visualization};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::atlas::{self, FrameMap, Rect, Size};
+use crate::bitmap::RgbaImage;
+use crate::runtime;
+
+/// Largest decompressed file inside a bundle, a raw spritesheet of the largest sprite size.
+const MAX_FILE_LENGTH: u64 = MAX_SPRITE_SIZE as u64 * MAX_SPRITE_SIZE as u64 * 4;
+
+/// Multi-file container read by the Nitro renderer.
+///
+/// Layout, all integers big endian: file count (u16), then per file the name length (u16), the UTF-8
+/// name, the compressed length (u32) and the zlib compressed content.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct NitroBundle {
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl NitroBundle {
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&(self.files.len() as u16).to_be_bytes());
+        for (name, content) in self.files.iter() {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content)?;
+            let compressed = encoder.finish()?;
+
+            buffer.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            buffer.extend_from_slice(name.as_bytes());
+            buffer.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+            buffer.extend_from_slice(&compressed);
+        }
+        Ok(buffer)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut cursor = data;
+        let file_count = read_u16(&mut cursor)?;
+        let mut files = Vec::with_capacity(file_count as usize);
+        for _ in 0..file_count {
+            let name_length = read_u16(&mut cursor)? as usize;
+            let name = String::from_utf8(take(&mut cursor, name_length)?.to_vec())
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            let compressed_length = read_u32(&mut cursor)? as usize;
+            let compressed = take(&mut cursor, compressed_length)?;
+
+            // NOTE; Bundles come from other retros too, a small zlib stream must not exhaust memory.
+            let mut content = Vec::new();
+            ZlibDecoder::new(compressed)
+                .take(MAX_FILE_LENGTH + 1)
+                .read_to_end(&mut content)?;
+            if content.len() as u64 > MAX_FILE_LENGTH {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} exceeds {} bytes", name, MAX_FILE_LENGTH),
+                ));
+            }
+            files.push((name, content));
+        }
+        Ok(NitroBundle { files })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.files
+            .iter()
+            .find(|(file_name, _)| file_name == name)
+            .map(|(_, content)| &content[..])
+    }
+}
+
+fn take<'a>(cursor: &mut &'a [u8], length: usize) -> io::Result<&'a [u8]> {
+    if cursor.len() < length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Nitro bundle is truncated",
+        ));
+    }
+    let (taken, rest) = cursor.split_at(length);
+    *cursor = rest;
+    Ok(taken)
+}
+
+fn read_u16(cursor: &mut &[u8]) -> io::Result<u16> {
+    let bytes = take(cursor, 2)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(cursor: &mut &[u8]) -> io::Result<u32> {
+    let bytes = take(cursor, 4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Asset type of furniture bundles, the renderer also loads pets, figures and effects.
+pub(crate) const FURNITURE_ASSET_TYPE: &str = "furniture";
+
+/// Asset description of a Nitro bundle, mirrors `IAssetData` of the Nitro renderer.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroAssetData {
+    /// Kind of asset, always [`FURNITURE_ASSET_TYPE`] for asset packs from `hof_furni`.
+    #[serde(rename = "type")]
+    pub asset_type: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logic_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visualization_type: Option<String>,
+    pub assets: BTreeMap<String, NitroAsset>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logic: Option<NitroLogic>,
+    pub visualizations: Vec<NitroVisualization>,
+    pub spritesheet: FrameMap,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroAsset {
+    pub x: i32,
+    pub y: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub flip_h: bool,
+    pub flip_v: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroLogic {
+    pub model: NitroModel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<logic::Action>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credits: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sound_sample: Option<NitroSoundSample>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub particle_systems: Vec<NitroParticleSystem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct NitroModel {
+    pub dimensions: NitroDimensions,
+    /// Allowed directions in degrees.
+    pub directions: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct NitroDimensions {
+    pub x: u32,
+    pub y: u32,
+    pub z: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroSoundSample {
+    pub id: u32,
+    pub no_pitch: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroParticleSystem {
+    pub size: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canvas_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset_y: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blend: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bg_color: Option<String>,
+    pub emitters: Vec<NitroEmitter>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroEmitter {
+    pub id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sprite_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_num_particles: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub particles_per_frame: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst_pulse: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuse_time: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simulation: Option<logic::Simulation>,
+    pub particles: Vec<NitroParticle>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroParticle {
+    pub is_emitter: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub life_time: Option<u32>,
+    pub fade: bool,
+    pub frames: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroVisualization {
+    pub angle: u32,
+    pub layer_count: u32,
+    pub size: u32,
+    pub layers: BTreeMap<String, NitroLayer>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub colors: BTreeMap<String, NitroColor>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub directions: BTreeMap<String, NitroDirection>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub animations: BTreeMap<String, NitroAnimation>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroLayer {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub z: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ink: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignore_mouse: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct NitroColor {
+    pub layers: BTreeMap<String, NitroColorLayer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct NitroColorLayer {
+    /// RGB value as integer.
+    pub color: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct NitroDirection {
+    pub layers: BTreeMap<String, NitroLayer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroAnimation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transition_to: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transition_from: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub immediate_change_from: Option<String>,
+    pub layers: BTreeMap<String, NitroAnimationLayer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroAnimationLayer {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loop_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_repeat: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub random: Option<bool>,
+    pub frame_sequences: BTreeMap<String, NitroFrameSequence>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroFrameSequence {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loop_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub random: Option<bool>,
+    pub frames: BTreeMap<String, NitroFrame>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NitroFrame {
+    pub id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub random_x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub random_y: Option<i32>,
+}
+
+/// Name of an asset inside the spritesheet, the asset name prefixed with the bundle name.
+pub(crate) fn frame_name(bundle_name: &str, asset_name: &str) -> String {
+    format!("{}_{}", bundle_name, asset_name)
+}
+
+pub(crate) fn convert_assets(asset_list: &AssetList) -> BTreeMap<String, NitroAsset> {
+    asset_list
+        .items
+        .iter()
+        .map(|asset| {
+            let converted = NitroAsset {
+                x: asset.x,
+                y: asset.y,
+                source: asset.source.clone(),
+                flip_h: asset.is_flipped_horizontally(),
+                flip_v: asset.is_flipped_vertically(),
+            };
+            (asset.name.clone(), converted)
+        })
+        .collect()
+}
+
+pub(crate) fn convert_logic(object_data: &ObjectData) -> NitroLogic {
+    let dimensions = object_data
+        .dimensions()
+        .map(|dimensions| NitroDimensions {
+            x: dimensions.x,
+            y: dimensions.y,
+            z: dimensions.z.unwrap_or(0.0),
+        })
+        .unwrap_or(NitroDimensions { x: 1, y: 1, z: 0.0 });
+
+    let particle_systems = object_data
+        .particle_systems
+        .iter()
+        .flat_map(|systems| systems.items.iter())
+        .map(|system| NitroParticleSystem {
+            size: system.size,
+            canvas_id: system.canvas_id,
+            offset_y: system.offset_y,
+            blend: system.blend,
+            bg_color: system.bgcolor.clone(),
+            emitters: system
+                .emitters
+                .iter()
+                .map(|emitter| NitroEmitter {
+                    id: emitter.id,
+                    name: emitter.name.clone(),
+                    sprite_id: emitter.sprite_id,
+                    max_num_particles: emitter.max_num_particles,
+                    particles_per_frame: emitter.particles_per_frame,
+                    burst_pulse: emitter.burst_pulse,
+                    fuse_time: emitter.fuse_time,
+                    simulation: emitter.simulation.clone(),
+                    particles: emitter
+                        .particles
+                        .items
+                        .iter()
+                        .map(|particle| NitroParticle {
+                            is_emitter: particle.is_emitter.unwrap_or(false),
+                            life_time: particle.life_time,
+                            fade: particle.fade.unwrap_or(false),
+                            frames: particle
+                                .frames
+                                .iter()
+                                .map(|frame| frame.name.clone())
+                                .collect(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    NitroLogic {
+        model: NitroModel {
+            dimensions,
+            directions: object_data
+                .model
+                .directions
+                .items
+                .iter()
+                .map(|direction| direction.id)
+                .collect(),
+        },
+        action: object_data.action.clone(),
+        credits: object_data
+            .credits
+            .as_ref()
+            .map(|credits| credits.value.clone()),
+        sound_sample: object_data
+            .sound
+            .as_ref()
+            .and_then(|sound| sound.samples.first())
+            .map(|sample| NitroSoundSample {
+                id: sample.id,
+                no_pitch: false,
+            }),
+        particle_systems,
+    }
+}
+
+fn convert_layer(layer: &visualization::Layer) -> NitroLayer {
+    NitroLayer {
+        z: layer.z,
+        ink: layer.ink.as_ref().map(|ink| ink.as_str().to_string()),
+        alpha: layer.alpha,
+        ignore_mouse: layer.ignore_mouse,
+        tag: layer.tag.clone(),
+    }
+}
+
+fn convert_layers(layers: &[visualization::Layer]) -> BTreeMap<String, NitroLayer> {
+    layers
+        .iter()
+        .map(|layer| (layer.id.to_string(), convert_layer(layer)))
+        .collect()
+}
+
+pub(crate) fn convert_visualization(visualization: &Visualization) -> NitroVisualization {
+    NitroVisualization {
+        angle: visualization.angle,
+        layer_count: visualization.layer_count,
+        size: visualization.size,
+        layers: convert_layers(&visualization.layers.items),
+        colors: visualization
+            .colors
+            .items
+            .iter()
+            .map(|color| {
+                let layers = color
+                    .layers
+                    .iter()
+                    .map(|layer| {
+                        let rgb = layer.rgb().unwrap_or([255, 255, 255]);
+                        let value = (u32::from(rgb[0]) << 16)
+                            | (u32::from(rgb[1]) << 8)
+                            | u32::from(rgb[2]);
+                        (layer.id.to_string(), NitroColorLayer { color: value })
+                    })
+                    .collect();
+                (color.id.to_string(), NitroColor { layers })
+            })
+            .collect(),
+        directions: visualization
+            .directions
+            .items
+            .iter()
+            .map(|direction| {
+                let layers = convert_layers(&direction.layers);
+                (direction.id.to_string(), NitroDirection { layers })
+            })
+            .collect(),
+        animations: visualization
+            .animations
+            .items
+            .iter()
+            .map(|animation| {
+                let layers = animation
+                    .layers
+                    .iter()
+                    .map(|layer| {
+                        let frame_sequences = layer
+                            .frame_sequences
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, sequence)| {
+                                let frames = sequence
+                                    .frames
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(idx, frame)| {
+                                        let converted = NitroFrame {
+                                            id: frame.id,
+                                            x: frame.x,
+                                            y: frame.y,
+                                            random_x: frame.random_x,
+                                            random_y: frame.random_y,
+                                        };
+                                        (idx.to_string(), converted)
+                                    })
+                                    .collect();
+                                let converted = NitroFrameSequence {
+                                    loop_count: sequence.loop_count,
+                                    random: sequence.random,
+                                    frames,
+                                };
+                                (idx.to_string(), converted)
+                            })
+                            .collect();
+                        let converted = NitroAnimationLayer {
+                            loop_count: layer.loop_count,
+                            frame_repeat: layer.frame_repeat,
+                            random: layer.random,
+                            frame_sequences,
+                        };
+                        (layer.id.to_string(), converted)
+                    })
+                    .collect();
+                let converted = NitroAnimation {
+                    transition_to: animation.transition_to,
+                    transition_from: animation.transition_from,
+                    immediate_change_from: animation.immediate_change_from.clone(),
+                    layers,
+                };
+                (animation.id.to_string(), converted)
+            })
+            .collect(),
+    }
+}
+
+/// Builds the asset description of a bundle from the parsed documents of an asset pack.
+///
+/// NOTE; Visualization size 1 only describes the catalog icon, Nitro doesn't use it.
+pub(crate) fn convert(
+    name: &str,
+    index: Option<&IndexData>,
+    asset_list: &AssetList,
+    object_data: Option<&ObjectData>,
+    visualizations: &[Visualization],
+    spritesheet: FrameMap,
+) -> NitroAssetData {
+    NitroAssetData {
+        asset_type: String::from(FURNITURE_ASSET_TYPE),
+        name: String::from(name),
+        logic_type: index.map(|index| index.logic.clone()),
+        visualization_type: index.map(|index| index.visualization.clone()),
+        assets: convert_assets(asset_list),
+        logic: object_data.map(convert_logic),
+        visualizations: visualizations
+            .iter()
+            .filter(|visualization| visualization.size != 1)
+            .map(convert_visualization)
+            .collect(),
+        spritesheet,
+    }
+}
+
+/// Largest edge of a sliced sprite, the same limit as packed atlases.
+const MAX_SPRITE_SIZE: u32 = atlas::MAX_SIZE;
+
+/// Frame of a spritesheet, only the fields needed to cut out the sprite.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SheetFrame {
+    frame: Rect,
+    #[serde(default)]
+    rotated: bool,
+    sprite_source_size: Option<Rect>,
+    source_size: Option<Size>,
+}
+
+/// Asset description of a bundle, only the fields needed to restore the loose images.
+#[derive(Debug, Clone, Deserialize)]
+struct SheetDocument {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    assets: BTreeMap<String, NitroAsset>,
+    spritesheet: Option<SheetFrames>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SheetFrames {
+    #[serde(default)]
+    frames: BTreeMap<String, SheetFrame>,
+}
+
+/// Cuts every frame out of the spritesheet, trimmed frames are restored to their source size.
+///
+/// NOTE; Rotated frames are stored turned 90 degrees clockwise, they're turned back. The frame map comes
+/// from the bundle, so its geometry is checked before any pixel is touched.
+fn slice_spritesheet(
+    frames: BTreeMap<String, SheetFrame>,
+    spritesheet: &RgbaImage,
+) -> Result<Vec<(String, RgbaImage)>, String> {
+    let pixel = |x: u32, y: u32| -> [u8; 4] {
+        if x >= spritesheet.width || y >= spritesheet.height {
+            return [0, 0, 0, 0];
+        }
+        let idx = (y as usize * spritesheet.width as usize + x as usize) * 4;
+        let mut value = [0; 4];
+        value.copy_from_slice(&spritesheet.pixels[idx..idx + 4]);
+        value
+    };
+
+    let mut sprites = Vec::with_capacity(frames.len());
+    for (name, frame) in frames.into_iter() {
+        let out_of_range = || format!("frame {} has out of range geometry", name);
+        let rect = frame.frame;
+        let placement = frame.sprite_source_size.unwrap_or(Rect {
+            x: 0,
+            y: 0,
+            w: rect.w,
+            h: rect.h,
+        });
+        let (placed_right, placed_bottom) = match (
+            placement.x.checked_add(rect.w),
+            placement.y.checked_add(rect.h),
+        ) {
+            (Some(right), Some(bottom)) => (right, bottom),
+            _ => return Err(out_of_range()),
+        };
+        // NOTE; Rotated frames cover the swapped extent on the spritesheet.
+        let extent = std::cmp::max(rect.w, rect.h);
+        if rect.x.checked_add(extent).is_none() || rect.y.checked_add(extent).is_none() {
+            return Err(out_of_range());
+        }
+        let size = frame.source_size.unwrap_or(Size {
+            w: placed_right,
+            h: placed_bottom,
+        });
+        if size.w > MAX_SPRITE_SIZE || size.h > MAX_SPRITE_SIZE {
+            return Err(format!(
+                "frame {} of {}x{} pixels exceeds {} pixels",
+                name, size.w, size.h, MAX_SPRITE_SIZE
+            ));
+        }
+        let length = (size.w as usize)
+            .checked_mul(size.h as usize)
+            .and_then(|area| area.checked_mul(4))
+            .ok_or_else(out_of_range)?;
+
+        let mut image = RgbaImage {
+            width: size.w,
+            height: size.h,
+            pixels: vec![0; length],
+        };
+        for y in 0..rect.h {
+            for x in 0..rect.w {
+                let value = match frame.rotated {
+                    true => pixel(rect.x + rect.h - 1 - y, rect.y + x),
+                    false => pixel(rect.x + x, rect.y + y),
+                };
+                let (target_x, target_y) = (placement.x + x, placement.y + y);
+                if target_x >= size.w || target_y >= size.h {
+                    continue;
+                }
+                let idx = (target_y as usize * size.w as usize + target_x as usize) * 4;
+                image.pixels[idx..idx + 4].copy_from_slice(&value);
+            }
+        }
+        sprites.push((name, image));
+    }
+    Ok(sprites)
+}
+
+/// File name of an asset image, asset names come from the bundle so only the file name part is kept.
+fn image_file_name(asset_name: &str) -> Result<String, String> {
+    Path::new(asset_name)
+        .file_name()
+        .map(|file_name| format!("{}.png", file_name.to_string_lossy()))
+        .ok_or_else(|| format!("unusable asset name {:?}", asset_name))
+}
+
+/// Follows the chain of aliases towards the asset which owns a frame, see [`AssetList::resolve_source`].
+fn resolve_source<'a>(assets: &'a BTreeMap<String, NitroAsset>, name: &'a str) -> &'a str {
+    let mut current = name;
+    for _ in 0..8 {
+        match assets.get(current).and_then(|asset| asset.source.as_ref()) {
+            Some(source) if source != current && assets.contains_key(source) => current = source,
+            _ => break,
+        }
+    }
+    current
+}
+
+/// Restores the images and manifest the SWF extraction writes, out of the asset description and spritesheet.
+///
+/// Frames lose the bundle name prefix of [`frame_name`], aliases are copied from their source and flipped
+/// when requested. Returns the images by file name, together with the manifest listing every asset.
+pub(crate) fn restore_assets(
+    asset_data: &[u8],
+    spritesheet: &RgbaImage,
+) -> Result<(Vec<(String, RgbaImage)>, AssetManifest), String> {
+    let document: SheetDocument =
+        serde_json::from_slice(asset_data).map_err(|error| error.to_string())?;
+    let frames = document
+        .spritesheet
+        .map(|sheet| sheet.frames)
+        .unwrap_or_default();
+    let prefix = frame_name(&document.name, "");
+    let sprites: BTreeMap<String, RgbaImage> = slice_spritesheet(frames, spritesheet)?
+        .into_iter()
+        .map(|(name, image)| match name.starts_with(&prefix) {
+            true => (name[prefix.len()..].to_string(), image),
+            false => (name, image),
+        })
+        .collect();
+
+    // NOTE; Frames without asset description are still written, they just aren't part of the manifest.
+    let mut images = Vec::with_capacity(sprites.len());
+    for (name, image) in sprites.iter() {
+        if !document.assets.contains_key(name) {
+            images.push((image_file_name(name)?, image.clone()));
+        }
+    }
+
+    let mut manifest = AssetManifest::default();
+    for (name, asset) in document.assets.iter() {
+        let source_name = resolve_source(&document.assets, name);
+        let image = match sprites.get(source_name) {
+            Some(image) if source_name == name => image.clone(),
+            Some(image) => {
+                let image = match asset.flip_h {
+                    true => image.flip_horizontal(),
+                    false => image.clone(),
+                };
+                match asset.flip_v {
+                    true => image.flip_vertical(),
+                    false => image,
+                }
+            }
+            None => continue,
+        };
+
+        let file_name = image_file_name(name)?;
+        images.push((file_name.clone(), image));
+        manifest.assets.push(ExtractedAsset {
+            name: name.clone(),
+            file: file_name,
+            x: asset.x,
+            y: asset.y,
+            source: asset.source.clone(),
+            flip_h: asset.flip_h,
+            flip_v: asset.flip_v,
+        });
+    }
+    Ok((images, manifest))
+}
+
+/// Writes every file of the bundle into the destination folder, optionally followed by the loose images
+/// restored from the spritesheet.
+pub(crate) fn unpack(
+    bundle_path: &Path,
+    destination: &Path,
+    slice: bool,
+    logger: &slog::Logger,
+) -> Result<(), runtime::Error> {
+    let data = fs::read(bundle_path).map_err(runtime::Error::Io)?;
+    let invalid = |reason: String| runtime::Error::InvalidBundle(bundle_path.to_path_buf(), reason);
+    let bundle = NitroBundle::from_bytes(&data).map_err(|error| invalid(error.to_string()))?;
+    fs::create_dir_all(destination).map_err(runtime::Error::Io)?;
+
+    for (name, content) in bundle.files.iter() {
+        // NOTE; Only the file name is used, bundles can't write outside the destination.
+        let file_name = Path::new(name)
+            .file_name()
+            .ok_or_else(|| invalid(format!("unusable file name {:?}", name)))?;
+        fs::write(destination.join(file_name), content).map_err(runtime::Error::Io)?;
+        slog::info!(logger, "Bundle file"; "name" => name, "bytes" => content.len());
+    }
+
+    if !slice {
+        return Ok(());
+    }
+
+    let json_file = bundle
+        .files
+        .iter()
+        .find(|(name, _)| name.ends_with(".json"));
+    let png_file = bundle.files.iter().find(|(name, _)| name.ends_with(".png"));
+    let (asset_data, spritesheet) = match (json_file, png_file) {
+        (Some((_, asset_data)), Some((_, png_data))) => (
+            asset_data,
+            RgbaImage::decode_png(png_data).map_err(runtime::Error::Image)?,
+        ),
+        _ => {
+            slog::warn!(logger, "Bundle holds no spritesheet to slice");
+            return Ok(());
+        }
+    };
+
+    let sprites_path = destination.join("sprites");
+    fs::create_dir_all(&sprites_path).map_err(runtime::Error::Io)?;
+    let (images, manifest) = restore_assets(asset_data, &spritesheet).map_err(invalid)?;
+    for (file_name, image) in images.iter() {
+        let png_data = image.encode_png().map_err(runtime::Error::Image)?;
+        fs::write(sprites_path.join(file_name), png_data).map_err(runtime::Error::Io)?;
+    }
+    let manifest_file =
+        fs::File::create(sprites_path.join(assets::MANIFEST_FILE)).map_err(runtime::Error::Io)?;
+    serde_json::to_writer_pretty(manifest_file, &manifest)
+        .map_err(|error| runtime::Error::Io(error.into()))?;
+    slog::info!(logger, "Spritesheet sliced"; "images" => images.len());
+    Ok(())
+}
@@ -0,0 +1,635 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use bobba_asset_extractor::assets::{self, AssetManifest};
+use bobba_asset_extractor::visualization::{
+    self, AnimationLayer, Ink, Visualization, VisualizationData,
+};
+
+use crate::asset_extraction::AssetPack;
+use crate::bitmap::RgbaImage;
+use crate::furni_document;
+use crate::runtime;
+
+/// Size of the previews written after extraction.
+pub(crate) const PREVIEW_SIZE: u32 = 64;
+
+/// Selects what is drawn: zoom level, direction index, animation state and frame, and color variant.
+///
+/// NOTE; Color 0 is the undyed furniture, it never tints.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Pose {
+    pub size: u32,
+    pub direction: u32,
+    pub state: u32,
+    pub frame: u32,
+    pub color: u32,
+}
+
+/// Layer image positioned relative to the furniture origin, ready to be composed.
+#[derive(Debug, Clone)]
+pub(crate) struct Placement<'a> {
+    pub image: &'a RgbaImage,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub ink: Ink,
+    pub alpha: u8,
+    pub tint: Option<[u8; 3]>,
+}
+
+/// Area covered by a set of placements, relative to the furniture origin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Bounds {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+/// Composes furniture images out of the extracted layer images.
+pub(crate) struct Renderer {
+    asset_name: String,
+    visualizations: VisualizationData,
+    manifest: AssetManifest,
+    images: HashMap<String, RgbaImage>,
+}
+
+impl Bounds {
+    pub fn union(self, other: Bounds) -> Bounds {
+        Bounds {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    pub fn of(placements: &[Placement]) -> Option<Bounds> {
+        placements
+            .iter()
+            .map(|placement| Bounds {
+                left: placement.x,
+                top: placement.y,
+                right: placement.x + placement.image.width as i32,
+                bottom: placement.y + placement.image.height as i32,
+            })
+            .fold(None, |bounds: Option<Bounds>, next| {
+                Some(bounds.map_or(next, |bounds| bounds.union(next)))
+            })
+    }
+
+    pub fn width(&self) -> u32 {
+        (self.right - self.left).max(0) as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        (self.bottom - self.top).max(0) as u32
+    }
+}
+
+impl Renderer {
+    /// Takes decoded images keyed by asset name, aliases must already be materialized (and flipped).
+    pub fn new(
+        asset_name: &str,
+        visualizations: VisualizationData,
+        manifest: AssetManifest,
+        images: HashMap<String, RgbaImage>,
+    ) -> Self {
+        Renderer {
+            asset_name: asset_name.to_string(),
+            visualizations,
+            manifest,
+            images,
+        }
+    }
+
+    /// Reads the visualization document, manifest and images from the extract folder of the asset pack.
+    pub fn load(
+        extract_path: &Path,
+        asset_name: &str,
+        logger: &slog::Logger,
+    ) -> Result<Option<Self>, runtime::Error> {
+        let visualizations = match furni_document::load_document(
+            &extract_path.join(format!("{}_visualization.xml", asset_name)),
+            visualization::from_reader,
+            logger,
+        ) {
+            Some(visualizations) => visualizations,
+            None => return Ok(None),
+        };
+        let manifest: AssetManifest = match fs::read(extract_path.join(assets::MANIFEST_FILE)) {
+            Ok(data) => {
+                serde_json::from_slice(&data).map_err(|error| runtime::Error::Io(error.into()))?
+            }
+            Err(_) => return Ok(None),
+        };
+
+        let mut images = HashMap::with_capacity(manifest.assets.len());
+        for asset in manifest.assets.iter() {
+            let data = fs::read(extract_path.join(&asset.file)).map_err(runtime::Error::Io)?;
+            let image = RgbaImage::decode_png(&data).map_err(runtime::Error::Image)?;
+            images.insert(asset.name.clone(), image);
+        }
+        Ok(Some(Renderer::new(
+            asset_name,
+            visualizations,
+            manifest,
+            images,
+        )))
+    }
+
+    pub fn visualization(&self, size: u32) -> Option<&Visualization> {
+        self.visualizations.visualization(size)
+    }
+
+    /// Frame id shown by the layer, following the frame sequences of the animation for the state.
+    ///
+    /// NOTE; Every frame is repeated `frameRepeat` ticks and every sequence `loopCount` times. A layer with
+    /// a `loopCount` holds its last frame after playing that many times, without one it loops forever.
+    fn frame_id(
+        visualization: &Visualization,
+        layer_id: u32,
+        state: u32,
+        frame: u32,
+    ) -> (u32, i32, i32) {
+        let animation_layer = visualization
+            .animation(state)
+            .and_then(|animation| animation.layers.iter().find(|layer| layer.id == layer_id));
+        let animation_layer = match animation_layer {
+            Some(layer) => layer,
+            None => return (0, 0, 0),
+        };
+
+        let frames = sequence_frames(animation_layer);
+        if frames.is_empty() {
+            return (0, 0, 0);
+        }
+        let repeat = animation_layer.frame_repeat.unwrap_or(1).max(1);
+        let index = (frame / repeat) as usize;
+        let index = match animation_layer.loop_count {
+            Some(loops) if loops > 0 && index >= frames.len() * loops as usize => frames.len() - 1,
+            _ => index % frames.len(),
+        };
+        let selected = frames[index];
+        (
+            selected.id,
+            selected.x.unwrap_or(0),
+            selected.y.unwrap_or(0),
+        )
+    }
+
+    /// Positions every visible layer for the pose, sorted bottom to top.
+    pub fn placements(&self, pose: &Pose) -> Vec<Placement> {
+        let visualization = match self.visualization(pose.size) {
+            Some(visualization) => visualization,
+            None => return Vec::new(),
+        };
+        let color = match pose.color {
+            0 => None,
+            id => visualization.color(id),
+        };
+
+        let mut placements = Vec::new();
+        for layer_id in 0..visualization.layer_count {
+            let layer = visualization.layer(pose.direction, layer_id);
+            let (frame_id, frame_x, frame_y) =
+                Renderer::frame_id(visualization, layer_id, pose.state, pose.frame);
+            // NOTE; Layers are lettered, layer 0 is `a`.
+            let layer_letter = (b'a' + (layer_id % 26) as u8) as char;
+            let name = format!(
+                "{}_{}_{}_{}_{}",
+                self.asset_name, pose.size, layer_letter, pose.direction, frame_id
+            );
+
+            let (asset, image) = match (self.manifest.get(&name), self.images.get(&name)) {
+                (Some(asset), Some(image)) => (asset, image),
+                _ => continue,
+            };
+            // NOTE; Flipped aliases mirror their registration point too, the image is already flipped.
+            let x = match asset.flip_h {
+                true => asset.x - image.width as i32,
+                false => -asset.x,
+            };
+            let y = match asset.flip_v {
+                true => asset.y - image.height as i32,
+                false => -asset.y,
+            };
+
+            placements.push(Placement {
+                image,
+                x: x + frame_x,
+                y: y + frame_y,
+                z: layer.z.unwrap_or(0),
+                ink: layer.ink.unwrap_or(Ink::Copy),
+                alpha: layer.alpha.unwrap_or(255),
+                tint: color.and_then(|color| {
+                    color
+                        .layers
+                        .iter()
+                        .find(|color_layer| color_layer.id == layer_id)
+                        .and_then(|color_layer| color_layer.rgb())
+                }),
+            });
+        }
+        // NOTE; Stable sort keeps layer order for equal z.
+        placements.sort_by_key(|placement| placement.z);
+        placements
+    }
+
+    /// Composes the pose onto the smallest canvas holding every layer.
+    pub fn render(&self, pose: &Pose) -> Option<RgbaImage> {
+        let placements = self.placements(pose);
+        let bounds = Bounds::of(&placements)?;
+        Some(compose(&placements, bounds))
+    }
+}
+
+/// Frames of every sequence of the layer in play order, sequences repeated by their loop count.
+pub(crate) fn sequence_frames(layer: &AnimationLayer) -> Vec<&visualization::Frame> {
+    layer
+        .frame_sequences
+        .iter()
+        .flat_map(|sequence| {
+            let loops = sequence.loop_count.unwrap_or(1).max(1) as usize;
+            std::iter::repeat(sequence.frames.iter())
+                .take(loops)
+                .flatten()
+        })
+        .collect()
+}
+
+/// Draws the placements in order onto a canvas covering the bounds.
+///
+/// NOTE; The canvas is kept premultiplied so additive and subtractive inks work on transparent pixels.
+pub(crate) fn compose(placements: &[Placement], bounds: Bounds) -> RgbaImage {
+    let (width, height) = (bounds.width(), bounds.height());
+    let mut canvas = vec![[0f32; 4]; (width * height) as usize];
+
+    for placement in placements.iter() {
+        let layer_alpha = placement.alpha as f32 / 255.0;
+        let tint = placement
+            .tint
+            .map(|[r, g, b]| [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0])
+            .unwrap_or([1.0; 3]);
+        let image = placement.image;
+
+        for source_y in 0..image.height {
+            let target_y = placement.y - bounds.top + source_y as i32;
+            if target_y < 0 || target_y >= height as i32 {
+                continue;
+            }
+            for source_x in 0..image.width {
+                let target_x = placement.x - bounds.left + source_x as i32;
+                if target_x < 0 || target_x >= width as i32 {
+                    continue;
+                }
+
+                let idx = ((source_y * image.width + source_x) * 4) as usize;
+                let pixel = &image.pixels[idx..idx + 4];
+                let alpha = pixel[3] as f32 / 255.0 * layer_alpha;
+                if alpha <= 0.0 {
+                    continue;
+                }
+                let source = [
+                    pixel[0] as f32 / 255.0 * tint[0] * alpha,
+                    pixel[1] as f32 / 255.0 * tint[1] * alpha,
+                    pixel[2] as f32 / 255.0 * tint[2] * alpha,
+                ];
+
+                let target = &mut canvas[(target_y as u32 * width + target_x as u32) as usize];
+                match placement.ink {
+                    Ink::Add => {
+                        for channel in 0..3 {
+                            target[channel] = (target[channel] + source[channel]).min(1.0);
+                        }
+                        target[3] = (target[3] + alpha).min(1.0);
+                    }
+                    Ink::Subtract => {
+                        for channel in 0..3 {
+                            target[channel] = (target[channel] - source[channel]).max(0.0);
+                        }
+                    }
+                    // NOTE; COPY is the regular source-over drawing in the client.
+                    Ink::Copy | Ink::Other(_) => {
+                        for channel in 0..3 {
+                            target[channel] = source[channel] + target[channel] * (1.0 - alpha);
+                        }
+                        target[3] = alpha + target[3] * (1.0 - alpha);
+                    }
+                }
+            }
+        }
+    }
+
+    let pixels = canvas
+        .iter()
+        .flat_map(|pixel| {
+            let alpha = pixel[3];
+            let straight = |channel: f32| match alpha > 0.0 {
+                true => ((channel / alpha).min(1.0) * 255.0).round() as u8,
+                false => 0,
+            };
+            vec![
+                straight(pixel[0]),
+                straight(pixel[1]),
+                straight(pixel[2]),
+                (alpha * 255.0).round() as u8,
+            ]
+        })
+        .collect();
+    RgbaImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+/// Writes a preview for every direction and color variant of the asset pack into `<extract folder>/preview/`.
+///
+/// NOTE; Files are named `<asset>_<size>_<direction>_<color>.png`, color 0 is the undyed furniture.
+pub(crate) fn write_previews(
+    pack: &AssetPack,
+    logger: &slog::Logger,
+) -> Result<(), runtime::Error> {
+    let extract_path = pack.extract_path();
+    let renderer = match Renderer::load(&extract_path, &pack.asset_name, logger)? {
+        Some(renderer) => renderer,
+        None => return Ok(()),
+    };
+    let directions = match renderer.visualization(PREVIEW_SIZE) {
+        Some(visualization) => visualization.direction_ids(),
+        None => return Ok(()),
+    };
+    let colors: BTreeSet<u32> = pack
+        .furniture
+        .iter()
+        .map(|item| item.color_index().unwrap_or(0))
+        .chain(std::iter::once(0))
+        .collect();
+
+    let preview_path = extract_path.join("preview");
+    fs::create_dir_all(&preview_path).map_err(runtime::Error::Io)?;
+    for &color in colors.iter() {
+        for &direction in directions.iter() {
+            let pose = Pose {
+                size: PREVIEW_SIZE,
+                direction,
+                color,
+                ..Pose::default()
+            };
+            let image = match renderer.render(&pose) {
+                Some(image) => image,
+                None => continue,
+            };
+            let png_data = image.encode_png().map_err(runtime::Error::Image)?;
+            let file_name = format!(
+                "{}_{}_{}_{}.png",
+                pack.asset_name, PREVIEW_SIZE, direction, color
+            );
+            fs::write(preview_path.join(file_name), png_data).map_err(runtime::Error::Io)?;
+        }
+    }
+    slog::debug!(logger, "Previews rendered"; "asset" => &pack.asset_name, "colors" => colors.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bobba_asset_extractor::assets::ExtractedAsset;
+    use bobba_asset_extractor::visualization::{Graphics, Layer, Layers};
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    fn image(width: u32, height: u32, pixels: &[[u8; 4]]) -> RgbaImage {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        RgbaImage {
+            width,
+            height,
+            pixels: pixels.iter().flatten().cloned().collect(),
+        }
+    }
+
+    fn placement(image: &RgbaImage, x: i32, y: i32) -> Placement {
+        Placement {
+            image,
+            x,
+            y,
+            z: 0,
+            ink: Ink::Copy,
+            alpha: 255,
+            tint: None,
+        }
+    }
+
+    fn asset(name: &str, x: i32, y: i32, flip_h: bool, flip_v: bool) -> ExtractedAsset {
+        ExtractedAsset {
+            name: name.to_string(),
+            file: format!("{}.png", name),
+            x,
+            y,
+            source: None,
+            flip_h,
+            flip_v,
+        }
+    }
+
+    fn renderer(layers: Vec<Layer>, assets: Vec<(ExtractedAsset, RgbaImage)>) -> Renderer {
+        let visualizations = VisualizationData {
+            furniture_type: String::from("test"),
+            graphics: Graphics {
+                visualizations: vec![Visualization {
+                    size: 64,
+                    layer_count: layers.len() as u32,
+                    layers: Layers { items: layers },
+                    ..Visualization::default()
+                }],
+            },
+        };
+        let images = assets
+            .iter()
+            .map(|(asset, image)| (asset.name.clone(), image.clone()))
+            .collect();
+        let manifest = AssetManifest {
+            assets: assets.into_iter().map(|(asset, _)| asset).collect(),
+        };
+        Renderer::new("test", visualizations, manifest, images)
+    }
+
+    fn pose() -> Pose {
+        Pose {
+            size: 64,
+            direction: 2,
+            ..Pose::default()
+        }
+    }
+
+    #[test]
+    fn layers_are_drawn_by_z() {
+        let layers = vec![
+            Layer {
+                id: 0,
+                z: Some(1),
+                ..Layer::default()
+            },
+            Layer {
+                id: 1,
+                ..Layer::default()
+            },
+        ];
+        let renderer = renderer(
+            layers,
+            vec![
+                (
+                    asset("test_64_a_2_0", 0, 0, false, false),
+                    image(2, 2, &[RED; 4]),
+                ),
+                (
+                    asset("test_64_b_2_0", -1, 0, false, false),
+                    image(2, 2, &[BLUE; 4]),
+                ),
+            ],
+        );
+
+        let placements = renderer.placements(&pose());
+        let order: Vec<i32> = placements.iter().map(|placement| placement.z).collect();
+        assert_eq!(order, vec![0, 1]);
+        assert_eq!(
+            renderer.render(&pose()),
+            Some(image(3, 2, &[RED, RED, BLUE, RED, RED, BLUE]))
+        );
+    }
+
+    #[test]
+    fn flipped_aliases_mirror_their_offset() {
+        let layers = vec![
+            Layer {
+                id: 0,
+                ..Layer::default()
+            },
+            Layer {
+                id: 1,
+                ..Layer::default()
+            },
+            Layer {
+                id: 2,
+                ..Layer::default()
+            },
+        ];
+        let renderer = renderer(
+            layers,
+            vec![
+                (
+                    asset("test_64_a_2_0", 2, 1, false, false),
+                    image(2, 1, &[RED, GREEN]),
+                ),
+                (
+                    asset("test_64_b_2_0", 2, 1, true, false),
+                    image(2, 1, &[GREEN, RED]),
+                ),
+                (
+                    asset("test_64_c_2_0", 0, 3, false, true),
+                    image(1, 2, &[BLUE, BLUE]),
+                ),
+            ],
+        );
+
+        let positions: Vec<(i32, i32)> = renderer
+            .placements(&pose())
+            .iter()
+            .map(|placement| (placement.x, placement.y))
+            .collect();
+        assert_eq!(positions, vec![(-2, -1), (0, -1), (0, 1)]);
+
+        let placements = renderer.placements(&pose());
+        let horizontal = &placements[..2];
+        let bounds = Bounds::of(horizontal).unwrap();
+        assert_eq!(
+            compose(horizontal, bounds),
+            image(4, 1, &[RED, GREEN, GREEN, RED])
+        );
+    }
+
+    #[test]
+    fn inks_and_alpha_blend() {
+        let base = image(1, 1, &[[100, 0, 0, 255]]);
+        let green = image(1, 1, &[[0, 50, 0, 255]]);
+        let subtracted = image(1, 1, &[[30, 60, 0, 255]]);
+        let white = image(1, 1, &[[255, 255, 255, 255]]);
+        let blue = image(1, 1, &[BLUE]);
+        let red = image(1, 1, &[RED]);
+        let bounds = Bounds {
+            left: 0,
+            top: 0,
+            right: 5,
+            bottom: 1,
+        };
+
+        let placements = vec![
+            // Additive ink on an opaque pixel.
+            placement(&base, 0, 0),
+            Placement {
+                ink: Ink::Add,
+                ..placement(&green, 0, 0)
+            },
+            // Subtractive ink clamps at zero.
+            placement(&base, 1, 0),
+            Placement {
+                ink: Ink::Subtract,
+                ..placement(&subtracted, 1, 0)
+            },
+            // Layer alpha on a transparent pixel keeps the color.
+            Placement {
+                alpha: 128,
+                ..placement(&white, 2, 0)
+            },
+            // Layer alpha over an opaque pixel blends both colors.
+            placement(&red, 3, 0),
+            Placement {
+                alpha: 128,
+                ..placement(&blue, 3, 0)
+            },
+            // Tints multiply the layer colors.
+            Placement {
+                tint: Some([0x3B, 0x5A, 0x82]),
+                ..placement(&white, 4, 0)
+            },
+        ];
+
+        assert_eq!(
+            compose(&placements, bounds),
+            image(
+                5,
+                1,
+                &[
+                    [100, 50, 0, 255],
+                    [70, 0, 0, 255],
+                    [255, 255, 255, 128],
+                    [127, 0, 128, 255],
+                    [0x3B, 0x5A, 0x82, 255],
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn uncovered_pixels_stay_transparent() {
+        let red = image(1, 1, &[RED]);
+        let bounds = Bounds {
+            left: -1,
+            top: 0,
+            right: 1,
+            bottom: 1,
+        };
+        assert_eq!(
+            compose(&[placement(&red, 0, 0)], bounds),
+            image(2, 1, &[CLEAR, RED])
+        );
+    }
+}
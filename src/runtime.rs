@@ -1,25 +1,74 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use reqwest::{blocking::Client, header};
+use reqwest::{blocking::Client, header, Url};
+
+use crate::hotel::Hotel;
 
 #[derive(Debug)]
 pub enum Error {
     HttpClient(reqwest::Error),
+    Io(std::io::Error),
+    InvalidUrl(String),
+    MissingRevision(Url),
+    EmptyBody(Url),
+    FurnitureXml(quick_xml::de::DeError),
+    FurnitureJson(serde_json::Error),
+    InvalidSwf(Url, String),
+    SwfParse(PathBuf, String),
+    Image(crate::bitmap::Error),
+    InvalidBundle(PathBuf, String),
+    NoCachedRevision(PathBuf),
+    InvalidHeader(String, String),
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
             Error::HttpClient(ref reqwest) => write!(f, "Http client error: {}", reqwest),
+            Error::Io(ref io) => write!(f, "IO error: {}", io),
+            Error::InvalidUrl(ref url) => write!(f, "Invalid url: {}", url),
+            Error::MissingRevision(ref url) => {
+                write!(f, "No revision could be derived from {}", url)
+            }
+            Error::EmptyBody(ref url) => write!(f, "Empty response body from {}", url),
+            Error::FurnitureXml(ref xml) => write!(f, "Invalid XML furniture data: {}", xml),
+            Error::FurnitureJson(ref json) => write!(f, "Invalid JSON furniture data: {}", json),
+            Error::InvalidSwf(ref url, ref reason) => {
+                write!(f, "Invalid SWF from {}: {}", url, reason)
+            }
+            Error::Image(ref image) => write!(f, "Image error: {}", image),
+            Error::SwfParse(ref path, ref reason) => {
+                write!(
+                    f,
+                    "SWF file {} couldn't be parsed: {}",
+                    path.display(),
+                    reason
+                )
+            }
+            Error::InvalidHeader(ref hotel, ref name) => {
+                write!(f, "Header {} of hotel {} is invalid", name, hotel)
+            }
+            Error::NoCachedRevision(ref path) => {
+                write!(
+                    f,
+                    "No furniture data was downloaded into {}",
+                    path.display()
+                )
+            }
+            Error::InvalidBundle(ref path, ref reason) => {
+                write!(f, "Nitro bundle {} is invalid: {}", path.display(), reason)
+            }
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+#[derive(Debug, Clone)]
 pub(crate) struct RuntimeData {
     base_data_path: PathBuf,
     revision: String,
+    pinned: bool,
 }
 
 impl RuntimeData {
@@ -27,18 +76,29 @@ impl RuntimeData {
         RuntimeData {
             base_data_path,
             revision,
+            pinned: false,
+        }
+    }
+
+    /// Creates runtime data for a build chosen by the user, the revision is also used for all asset packs.
+    pub fn pinned(base_data_path: PathBuf, revision: String) -> Self {
+        RuntimeData {
+            base_data_path,
+            revision,
+            pinned: true,
         }
     }
 
-    pub fn create_web_client() -> Result<Client, Error> {
+    /// Creates the client for requests towards the hotel, carrying its user agent and extra headers.
+    pub fn create_web_client(hotel: &Hotel) -> Result<Client, Error> {
+        let invalid_header =
+            |name: &str| Error::InvalidHeader(hotel.name.clone(), name.to_string());
+
         let mut request_headers = header::HeaderMap::new();
-        // WARN; User agent is required because Habbo blocks other asset requests!
-        // This header emulates a request from Firefox, but might need tweaking to work in the future.
         request_headers.insert(
             header::USER_AGENT,
-            header::HeaderValue::from_static(
-                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:74.0) Gecko/20100101 Firefox/74.0",
-            ),
+            header::HeaderValue::from_str(&hotel.user_agent)
+                .map_err(|_| invalid_header(header::USER_AGENT.as_str()))?,
         );
         request_headers.insert(
             header::ACCEPT,
@@ -46,6 +106,13 @@ impl RuntimeData {
                 "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
             ),
         );
+        for (name, value) in hotel.headers.iter() {
+            let header_name = header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| invalid_header(name))?;
+            let header_value =
+                header::HeaderValue::from_str(value).map_err(|_| invalid_header(name))?;
+            request_headers.insert(header_name, header_value);
+        }
 
         Client::builder()
             .default_headers(request_headers)
@@ -54,7 +121,27 @@ impl RuntimeData {
             .map_err(Error::HttpClient)
     }
 
+    /// Folder of the hotel, holding every revision folder and the asset packs.
+    pub fn get_base_data_path(&self) -> &Path {
+        &self.base_data_path
+    }
+
     pub fn get_data_path(&self) -> PathBuf {
         self.base_data_path.join(&self.revision)
     }
+
+    /// Folder holding the asset packs of all furniture revisions, mirroring the `hof_furni` url layout.
+    ///
+    /// NOTE; Asset packs are shared between furniture data revisions so they survive an update of the hotel.
+    pub fn get_asset_path(&self) -> PathBuf {
+        self.base_data_path.join("hof_furni")
+    }
+
+    pub fn get_revision(&self) -> &str {
+        &self.revision
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
 }
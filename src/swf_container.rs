@@ -0,0 +1,229 @@
+use std::borrow::Cow;
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+
+/// Size of the signature, version and file length fields shared by all SWF files.
+const HEADER_LENGTH: usize = 8;
+/// Size of the compressed length and LZMA properties following the common header of `ZWS` files.
+const LZMA_HEADER_LENGTH: usize = 4 + 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Compression {
+    /// `FWS`
+    None,
+    /// `CWS`
+    Zlib,
+    /// `ZWS`
+    Lzma,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SwfHeader {
+    pub compression: Compression,
+    pub version: u8,
+    /// Length of the file after decompression, including the common header.
+    pub uncompressed_length: u32,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+pub(crate) fn read_header(data: &[u8]) -> Result<SwfHeader, String> {
+    if data.len() < HEADER_LENGTH {
+        return Err(format!(
+            "{} bytes is too short for a SWF header",
+            data.len()
+        ));
+    }
+
+    let compression = match &data[0..3] {
+        b"FWS" => Compression::None,
+        b"CWS" => Compression::Zlib,
+        b"ZWS" => Compression::Lzma,
+        other => {
+            return Err(format!(
+                "Unknown signature {:x?}, starts with `{}`",
+                other,
+                String::from_utf8_lossy(&data[0..std::cmp::min(data.len(), 32)])
+            ))
+        }
+    };
+
+    Ok(SwfHeader {
+        compression,
+        version: data[3],
+        uncompressed_length: read_u32(data, 4),
+    })
+}
+
+/// Verifies the payload is a complete SWF file.
+///
+/// NOTE; This catches HTML error pages served with a success status and truncated transfers.
+pub(crate) fn validate(data: &[u8]) -> Result<(), String> {
+    let header = read_header(data)?;
+    let expected_body = (header.uncompressed_length as usize).saturating_sub(HEADER_LENGTH);
+
+    match header.compression {
+        Compression::None => {
+            if data.len() != header.uncompressed_length as usize {
+                return Err(format!(
+                    "Header announces {} bytes, received {}",
+                    header.uncompressed_length,
+                    data.len()
+                ));
+            }
+        }
+        Compression::Zlib => {
+            let mut decoder = ZlibDecoder::new(&data[HEADER_LENGTH..]);
+            let body_length = io::copy(&mut decoder, &mut io::sink())
+                .map_err(|error| format!("Corrupt zlib body: {}", error))?;
+            if body_length as usize != expected_body {
+                return Err(format!(
+                    "Header announces {} uncompressed bytes, body holds {}",
+                    expected_body, body_length
+                ));
+            }
+        }
+        Compression::Lzma => {
+            if data.len() < HEADER_LENGTH + LZMA_HEADER_LENGTH {
+                return Err(String::from("Missing LZMA header"));
+            }
+            let compressed_length = read_u32(data, HEADER_LENGTH) as usize;
+            let received = data.len() - HEADER_LENGTH - LZMA_HEADER_LENGTH;
+            if received < compressed_length {
+                return Err(format!(
+                    "Header announces {} compressed bytes, received {}",
+                    compressed_length, received
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts `ZWS` files into uncompressed `FWS` files, other files are returned as is.
+///
+/// NOTE; The swf crate is built without LZMA support. The SWF variant of the LZMA header drops the
+/// 64-bit uncompressed size of the LZMA-alone format and stores the compressed length in front of the
+/// 5 property bytes instead, the alone header is rebuilt before decoding.
+pub(crate) fn decompress(data: &[u8]) -> Result<Cow<[u8]>, String> {
+    let header = read_header(data)?;
+    if header.compression != Compression::Lzma {
+        return Ok(Cow::Borrowed(data));
+    }
+    if data.len() < HEADER_LENGTH + LZMA_HEADER_LENGTH {
+        return Err(String::from("Missing LZMA header"));
+    }
+
+    let body_length = (header.uncompressed_length as usize).saturating_sub(HEADER_LENGTH);
+    let properties = &data[HEADER_LENGTH + 4..HEADER_LENGTH + LZMA_HEADER_LENGTH];
+    let mut lzma_header = Vec::with_capacity(5 + 8);
+    lzma_header.extend_from_slice(properties);
+    lzma_header.extend_from_slice(&(body_length as u64).to_le_bytes());
+
+    let compressed = &data[HEADER_LENGTH + LZMA_HEADER_LENGTH..];
+    let mut input = io::BufReader::new(io::Cursor::new(lzma_header).chain(compressed));
+    let mut body = Vec::new();
+    lzma_rs::lzma_decompress(&mut input, &mut body)
+        .map_err(|error| format!("Corrupt LZMA body: {:?}", error))?;
+    if body.len() != body_length {
+        return Err(format!(
+            "Header announces {} uncompressed bytes, body holds {}",
+            body_length,
+            body.len()
+        ));
+    }
+
+    let mut movie = Vec::with_capacity(HEADER_LENGTH + body_length);
+    movie.extend_from_slice(b"FWS");
+    movie.push(header.version);
+    movie.extend_from_slice(&header.uncompressed_length.to_le_bytes());
+    movie.extend_from_slice(&body);
+    Ok(Cow::Owned(movie))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NOTE; The same movie in every container, the `ZWS` body ends with the LZMA-alone end marker.
+    const FWS: &[u8] = include_bytes!("../tests/fixtures/movie_fws.swf");
+    const CWS: &[u8] = include_bytes!("../tests/fixtures/movie_cws.swf");
+    const ZWS: &[u8] = include_bytes!("../tests/fixtures/movie_zws.swf");
+
+    #[test]
+    fn headers_are_read() {
+        let fixtures = [
+            (FWS, Compression::None),
+            (CWS, Compression::Zlib),
+            (ZWS, Compression::Lzma),
+        ];
+        for &(data, compression) in fixtures.iter() {
+            assert_eq!(
+                read_header(data),
+                Ok(SwfHeader {
+                    compression,
+                    version: 10,
+                    uncompressed_length: FWS.len() as u32,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn complete_files_are_valid() {
+        assert_eq!(validate(FWS), Ok(()));
+        assert_eq!(validate(CWS), Ok(()));
+        assert_eq!(validate(ZWS), Ok(()));
+    }
+
+    #[test]
+    fn truncated_files_are_invalid() {
+        for data in [FWS, CWS, ZWS].iter() {
+            assert!(validate(&data[..data.len() - 1]).is_err());
+        }
+        assert!(validate(&ZWS[..HEADER_LENGTH + 4]).is_err());
+    }
+
+    #[test]
+    fn error_pages_are_invalid() {
+        let page = b"<!DOCTYPE html><html><body>Not Found</body></html>";
+        assert!(read_header(page).is_err());
+        assert!(validate(page).is_err());
+        assert!(validate(b"FWS").is_err());
+    }
+
+    #[test]
+    fn uncompressed_and_zlib_files_are_kept() {
+        for &data in [FWS, CWS].iter() {
+            match decompress(data) {
+                Ok(Cow::Borrowed(kept)) => assert_eq!(kept, data),
+                other => panic!("Expected the file to be kept, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn lzma_files_are_decompressed() {
+        let movie = decompress(ZWS).unwrap();
+        assert_eq!(&movie[..], FWS);
+    }
+
+    #[test]
+    fn corrupt_lzma_files_fail() {
+        assert!(decompress(&ZWS[..HEADER_LENGTH + 4]).is_err());
+
+        // NOTE; Announcing a longer movie than the body holds must not pass unnoticed.
+        let mut longer = ZWS.to_vec();
+        longer[4..8].copy_from_slice(&(FWS.len() as u32 + 4).to_le_bytes());
+        assert!(decompress(&longer).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! Minimal HTTP server on localhost, stands in for the hotel and asset servers during tests.
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use reqwest::Url;
+
+/// Answer of the server to one request.
+#[derive(Debug, Clone)]
+pub(crate) enum Reply {
+    Response {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    },
+    /// Announces a longer body than is sent before closing the connection.
+    Truncated { length: usize, body: Vec<u8> },
+    /// Keeps the connection open without answering, the client is expected to time out.
+    Stall(Duration),
+}
+
+impl Reply {
+    pub fn ok<B: Into<Vec<u8>>>(body: B) -> Self {
+        Reply::Response {
+            status: 200,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Reply::Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn redirect(location: &str) -> Self {
+        Reply::Response {
+            status: 302,
+            headers: vec![(String::from("Location"), String::from(location))],
+            body: Vec::new(),
+        }
+    }
+}
+
+type Handler = dyn Fn(&str, usize) -> Reply + Send + Sync;
+
+/// Server answering every request through the handler, which receives the path and how often that path
+/// was requested before.
+pub(crate) struct TestServer {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&str, usize) -> Reply + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Local port unavailable");
+        let address = listener.local_addr().expect("Local address unavailable");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let server_requests = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let requests = Arc::clone(&server_requests);
+                let handler = Arc::clone(&handler);
+                thread::spawn(move || serve(stream, &*handler, &requests));
+            }
+        });
+
+        TestServer { address, requests }
+    }
+
+    pub fn url(&self, path: &str) -> Url {
+        Url::parse(&format!("http://{}{}", self.address, path)).expect("Valid test url")
+    }
+
+    /// Paths of all requests received so far, in order of arrival.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().expect("Poisoned request log!").clone()
+    }
+
+    pub fn request_count(&self, path: &str) -> usize {
+        self.requests()
+            .iter()
+            .filter(|other| *other == path)
+            .count()
+    }
+}
+
+fn read_path(stream: &mut TcpStream) -> Option<String> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).ok()?;
+        if read == 0 {
+            return None;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    head.lines().next()?.split(' ').nth(1).map(String::from)
+}
+
+fn serve(mut stream: TcpStream, handler: &Handler, requests: &Mutex<Vec<String>>) {
+    let path = match read_path(&mut stream) {
+        Some(path) => path,
+        None => return,
+    };
+    let attempt = {
+        let mut requests = requests.lock().expect("Poisoned request log!");
+        let attempt = requests.iter().filter(|other| **other == path).count();
+        requests.push(path.clone());
+        attempt
+    };
+
+    let (status, headers, length, body) = match handler(&path, attempt) {
+        Reply::Response {
+            status,
+            headers,
+            body,
+        } => (status, headers, body.len(), body),
+        Reply::Truncated { length, body } => (200, Vec::new(), length, body),
+        Reply::Stall(duration) => {
+            thread::sleep(duration);
+            return;
+        }
+    };
+
+    let mut response = format!("HTTP/1.1 {} Test\r\n", status);
+    for (name, value) in headers.iter() {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        length
+    ));
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.write_all(&body);
+    let _ = stream.flush();
+    let _ = stream.shutdown(Shutdown::Both);
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+
+/// Caps the amount of simultaneous connections towards each host, independent of the thread count.
+#[derive(Debug)]
+pub(crate) struct HostLimiter {
+    limit: usize,
+    active: Mutex<HashMap<String, usize>>,
+    released: Condvar,
+}
+
+/// Slot of a host connection, the slot is given back when the permit is dropped.
+pub(crate) struct HostPermit<'a> {
+    limiter: &'a HostLimiter,
+    host: String,
+}
+
+impl HostLimiter {
+    pub fn new(limit: usize) -> Self {
+        HostLimiter {
+            // NOTE; A limit of zero would block forever.
+            limit: std::cmp::max(limit, 1),
+            active: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a connection slot towards the host is available.
+    pub fn acquire(&self, host: &str) -> HostPermit<'_> {
+        let mut active = self.active.lock().expect("Poisoned host limiter!");
+        while active.get(host).cloned().unwrap_or(0) >= self.limit {
+            active = self.released.wait(active).expect("Poisoned host limiter!");
+        }
+        *active.entry(String::from(host)).or_insert(0) += 1;
+
+        HostPermit {
+            limiter: self,
+            host: String::from(host),
+        }
+    }
+}
+
+impl<'a> Drop for HostPermit<'a> {
+    fn drop(&mut self) {
+        let mut active = self.limiter.active.lock().expect("Poisoned host limiter!");
+        if let Some(count) = active.get_mut(&self.host) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.host);
+            }
+        }
+        self.limiter.released.notify_all();
+    }
+}
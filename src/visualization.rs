@@ -0,0 +1,332 @@
+//! Typed model of the `<asset name>_visualization.xml` document embedded in each furniture asset pack.
+
+use std::fmt;
+use std::io::BufRead;
+
+use quick_xml::de::DeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "visualizationData")]
+pub struct VisualizationData {
+    #[serde(rename = "type", default)]
+    pub furniture_type: String,
+    #[serde(default)]
+    pub graphics: Graphics,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Graphics {
+    #[serde(rename = "visualization", default)]
+    pub visualizations: Vec<Visualization>,
+}
+
+/// Visualization of the furniture at one zoom level, 32 or 64 pixels. Size 1 holds the catalog icon.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Visualization {
+    pub size: u32,
+    #[serde(rename = "layerCount")]
+    pub layer_count: u32,
+    #[serde(default)]
+    pub angle: u32,
+    #[serde(default)]
+    pub layers: Layers,
+    #[serde(default)]
+    pub directions: Directions,
+    #[serde(default)]
+    pub colors: Colors,
+    #[serde(default)]
+    pub animations: Animations,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Layers {
+    #[serde(rename = "layer", default)]
+    pub items: Vec<Layer>,
+}
+
+/// Blend mode of a layer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ink {
+    Copy,
+    Add,
+    Subtract,
+    Other(String),
+}
+
+/// Properties of one layer, all of them are optional because directions only override a subset.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    pub id: u32,
+    pub z: Option<i32>,
+    pub ink: Option<Ink>,
+    pub alpha: Option<u8>,
+    #[serde(rename = "ignoreMouse")]
+    pub ignore_mouse: Option<bool>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Directions {
+    #[serde(rename = "direction", default)]
+    pub items: Vec<Direction>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Direction {
+    pub id: u32,
+    #[serde(rename = "layer", default)]
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Colors {
+    #[serde(rename = "color", default)]
+    pub items: Vec<Color>,
+}
+
+/// Tints applied to layers when the furniture uses color variant `id`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Color {
+    pub id: u32,
+    #[serde(rename = "colorLayer", default)]
+    pub layers: Vec<ColorLayer>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColorLayer {
+    pub id: u32,
+    /// Hexadecimal RGB value, without leading `#`.
+    pub color: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Animations {
+    #[serde(rename = "animation", default)]
+    pub items: Vec<Animation>,
+}
+
+/// Animation of one state, transition states move from or towards another state.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Animation {
+    pub id: u32,
+    #[serde(rename = "transitionTo")]
+    pub transition_to: Option<u32>,
+    #[serde(rename = "transitionFrom")]
+    pub transition_from: Option<u32>,
+    #[serde(rename = "immediateChangeFrom")]
+    pub immediate_change_from: Option<String>,
+    #[serde(rename = "animationLayer", default)]
+    pub layers: Vec<AnimationLayer>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnimationLayer {
+    pub id: u32,
+    #[serde(rename = "loopCount")]
+    pub loop_count: Option<u32>,
+    #[serde(rename = "frameRepeat")]
+    pub frame_repeat: Option<u32>,
+    pub random: Option<bool>,
+    #[serde(rename = "frameSequence", default)]
+    pub frame_sequences: Vec<FrameSequence>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrameSequence {
+    #[serde(rename = "loopCount")]
+    pub loop_count: Option<u32>,
+    pub random: Option<bool>,
+    #[serde(rename = "frame", default)]
+    pub frames: Vec<Frame>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub id: u32,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    #[serde(rename = "randomX")]
+    pub random_x: Option<i32>,
+    #[serde(rename = "randomY")]
+    pub random_y: Option<i32>,
+}
+
+impl Ink {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Ink::Copy => "COPY",
+            Ink::Add => "ADD",
+            Ink::Subtract => "SUBTRACT",
+            Ink::Other(ref other) => other,
+        }
+    }
+}
+
+impl fmt::Display for Ink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Ink {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Ok(match value.to_ascii_uppercase().as_str() {
+            "COPY" => Ink::Copy,
+            "ADD" => Ink::Add,
+            "SUBTRACT" => Ink::Subtract,
+            _ => Ink::Other(value),
+        })
+    }
+}
+
+impl Serialize for Ink {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl Layer {
+    /// Applies the properties set on the override on top of this layer.
+    pub fn merge(&self, overrides: &Layer) -> Layer {
+        Layer {
+            id: self.id,
+            z: overrides.z.or(self.z),
+            ink: overrides.ink.clone().or_else(|| self.ink.clone()),
+            alpha: overrides.alpha.or(self.alpha),
+            ignore_mouse: overrides.ignore_mouse.or(self.ignore_mouse),
+            tag: overrides.tag.clone().or_else(|| self.tag.clone()),
+        }
+    }
+}
+
+impl ColorLayer {
+    pub fn rgb(&self) -> Option<[u8; 3]> {
+        let hex = self.color.trim_start_matches('#');
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+    }
+}
+
+impl VisualizationData {
+    pub fn visualization(&self, size: u32) -> Option<&Visualization> {
+        self.graphics
+            .visualizations
+            .iter()
+            .find(|visualization| visualization.size == size)
+    }
+}
+
+impl Visualization {
+    /// Properties of the layer when the furniture faces the direction, direction overrides included.
+    pub fn layer(&self, direction: u32, layer_id: u32) -> Layer {
+        let base = self
+            .layers
+            .items
+            .iter()
+            .find(|layer| layer.id == layer_id)
+            .cloned()
+            .unwrap_or(Layer {
+                id: layer_id,
+                ..Layer::default()
+            });
+
+        self.directions
+            .items
+            .iter()
+            .find(|item| item.id == direction)
+            .and_then(|item| item.layers.iter().find(|layer| layer.id == layer_id))
+            .map(|overrides| base.merge(overrides))
+            .unwrap_or(base)
+    }
+
+    pub fn direction_ids(&self) -> Vec<u32> {
+        self.directions
+            .items
+            .iter()
+            .map(|direction| direction.id)
+            .collect()
+    }
+
+    pub fn color(&self, color_id: u32) -> Option<&Color> {
+        self.colors.items.iter().find(|color| color.id == color_id)
+    }
+
+    pub fn animation(&self, state: u32) -> Option<&Animation> {
+        self.animations
+            .items
+            .iter()
+            .find(|animation| animation.id == state)
+    }
+}
+
+pub fn from_reader<R: BufRead>(reader: R) -> Result<VisualizationData, DeError> {
+    quick_xml::de::from_reader(reader)
+}
+
+pub fn from_str(document: &str) -> Result<VisualizationData, DeError> {
+    quick_xml::de::from_str(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/rare_dragonlamp_visualization.xml");
+
+    #[test]
+    fn fixture_is_parsed() {
+        let data = from_str(FIXTURE).unwrap();
+        assert_eq!(data.furniture_type, "rare_dragonlamp");
+        assert_eq!(data.graphics.visualizations.len(), 2);
+
+        let visualization = data.visualization(64).unwrap();
+        assert_eq!(visualization.layer_count, 3);
+        assert_eq!(visualization.angle, 45);
+        assert_eq!(visualization.direction_ids(), vec![2, 4]);
+        assert_eq!(visualization.layer(2, 2).tag.as_deref(), Some("flame"));
+        assert_eq!(
+            visualization.layer(4, 1),
+            Layer {
+                id: 1,
+                z: Some(-1),
+                ink: Some(Ink::Add),
+                alpha: Some(128),
+                ignore_mouse: Some(true),
+                tag: None,
+            }
+        );
+        assert_eq!(visualization.layer(2, 1).z, Some(1));
+        assert_eq!(
+            visualization.color(1).unwrap().layers[0].rgb(),
+            Some([0x3B, 0x5A, 0x82])
+        );
+
+        let animation = visualization.animation(1).unwrap();
+        assert_eq!(animation.transition_to, Some(2));
+        let layer = &animation.layers[0];
+        assert_eq!((layer.frame_repeat, layer.loop_count), (Some(2), Some(1)));
+        let frames = &layer.frame_sequences[0].frames;
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            (frames[1].id, frames[1].x, frames[1].y),
+            (1, Some(1), Some(-2))
+        );
+    }
+
+    #[test]
+    fn fixture_round_trips_through_json() {
+        let data = from_str(FIXTURE).unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        let parsed: VisualizationData = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, data);
+    }
+
+    #[test]
+    fn unknown_inks_are_kept() {
+        let layer: Layer = quick_xml::de::from_str(r#"<layer id="3" ink="darken"/>"#).unwrap();
+        assert_eq!(layer.ink, Some(Ink::Other(String::from("darken"))));
+        assert_eq!(layer.ink.unwrap().as_str(), "darken");
+    }
+}